@@ -0,0 +1,53 @@
+#![no_main]
+
+use bitcoin::{
+    consensus::{deserialize_partial, serialize},
+    p2p::message::RawNetworkMessage,
+};
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use p2p_handshake::p2p::btc::codec::{RawNetworkMessageCodec, HEADER_SIZE, MAX_MESSAGE_SIZE};
+use tokio_util::codec::Decoder;
+
+/// Decode `data` received `chunk` bytes at a time, returning the decoded messages and whether
+/// the stream was rejected
+fn decode(data: &[u8], chunk: usize) -> (Vec<RawNetworkMessage>, bool) {
+    let mut codec =
+        RawNetworkMessageCodec::new_client(([127, 0, 0, 1], 8333).into(), String::new()).unwrap();
+    let mut buf = BytesMut::new();
+    let mut messages = Vec::new();
+    for chunk in data.chunks(chunk) {
+        buf.extend_from_slice(chunk);
+        loop {
+            match codec.decode(&mut buf) {
+                Ok(Some(message)) => messages.push(message),
+                Ok(None) => break,
+                Err(_) => return (messages, true),
+            }
+        }
+        // Only the bytes of the incomplete message are kept, which can't exceed the limit
+        assert!(buf.len() <= data.len());
+        assert!(buf.len() <= MAX_MESSAGE_SIZE + HEADER_SIZE);
+    }
+    (messages, false)
+}
+
+fuzz_target!(|data: &[u8]| {
+    // The first byte picks how the stream is split into reads
+    let Some((split, data)) = data.split_first() else {
+        return;
+    };
+    let whole = decode(data, data.len().max(1));
+    let split = decode(data, *split as usize % 64 + 1);
+
+    // Verify that the decoding does not depend on how the bytes are received
+    assert_eq!(whole, split);
+
+    // Verify that every decoded message round-trips
+    for message in whole.0 {
+        let bytes = serialize(&message);
+        let (decoded, count) = deserialize_partial::<RawNetworkMessage>(&bytes).unwrap();
+        assert_eq!(count, bytes.len());
+        assert_eq!(decoded, message);
+    }
+});
@@ -0,0 +1,45 @@
+#![no_main]
+
+use alloy_rlp::{Decodable, Encodable};
+use futures::SinkExt;
+use libfuzzer_sys::fuzz_target;
+use p2p_handshake::p2p::eth::stream::P2PStream;
+use reth_eth_wire::{HelloMessage, P2PMessage};
+use reth_primitives::{bytes::Bytes, PeerId};
+use tokio::runtime::Runtime;
+use tokio_util::codec::{Decoder, LengthDelimitedCodec};
+
+thread_local! {
+    static RUNTIME: Runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+}
+
+fuzz_target!(|data: &[u8]| {
+    // Send the bytes as the first frame of the peer, over an in-memory duplex
+    let hello = HelloMessage::builder(PeerId::random()).build();
+    let res = RUNTIME.with(|runtime| {
+        runtime.block_on(async {
+            let (local, remote) = tokio::io::duplex(64 * 1024);
+            let mut remote = LengthDelimitedCodec::default().framed(remote);
+            let (res, sent) = tokio::join!(
+                P2PStream::new(LengthDelimitedCodec::default().framed(local))
+                    .handshake(hello, 1000),
+                remote.send(Bytes::copy_from_slice(data)),
+            );
+            sent.unwrap();
+            res
+        })
+    });
+
+    // Verify that a well-formed Hello completes the handshake and round-trips
+    if let Ok(P2PMessage::Hello(peer_hello)) = P2PMessage::decode(&mut &data[..]) {
+        assert!(res.is_ok(), "valid hello rejected: {:?}", res);
+
+        let mut buf = Vec::new();
+        P2PMessage::Hello(peer_hello.clone()).encode(&mut buf);
+        let decoded = P2PMessage::decode(&mut &buf[..]).unwrap();
+        assert_eq!(decoded, P2PMessage::Hello(peer_hello));
+    }
+});
@@ -1,17 +1,20 @@
 use clap::Parser;
 use p2p_handshake::{
     p2p::{config::Config, handshake},
-    telemetry::init_tracing,
+    telemetry::{init_metrics, init_tracing, shutdown_tracing},
 };
 
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
-    // Initialize tracing
-    init_tracing();
-
-    // Parse the CLI arguments and perform the P2P handshake for corresponding network
+    // Parse the CLI arguments and initialize tracing
     let config = Config::parse();
-    handshake(config).await?;
+    let _guard = init_tracing(config.tracing())?;
+    if let Some(metrics_addr) = config.metrics_addr {
+        init_metrics(metrics_addr)?;
+    }
 
-    Ok(())
+    // Perform the P2P handshake for corresponding network
+    let res = handshake(config).await;
+    shutdown_tracing();
+    res
 }
@@ -1,66 +1,700 @@
-use std::net::IpAddr;
+use std::{
+    fmt::Display,
+    future::Future,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
 
-use futures_util::TryFutureExt;
-use tokio::task::JoinHandle;
-use tracing::{error, info};
+use ::libp2p::Multiaddr;
+use hickory_resolver::TokioAsyncResolver;
+use reth_primitives::holesky_nodes;
+use tokio::{task::JoinHandle, time::MissedTickBehavior};
+use tracing::{debug, error, info, warn};
 
-use crate::p2p::{commands::Commands, config::Config, error::P2PError};
+use crate::{
+    p2p::{
+        beacon::network::{ForkDigest, Network},
+        btc::chain::{Chain, Profile},
+        commands::{
+            BtcCommands, BtcCrawlArgs, Commands, Discovery, EthCommands, EthCrawlArgs, HistoryArgs,
+            ReplayArgs,
+        },
+        config::Config,
+        connect::{Connector, Destination, Endpoints},
+        error::P2PError,
+        eth::target::Peer,
+        history::{History, HistoryQuery},
+        record::Session,
+        watch::{Monitor, Transition},
+    },
+    telemetry::{self, collect_phases, HandshakeMetrics},
+};
 
+pub mod api;
+pub mod beacon;
 pub mod btc;
 mod commands;
 pub mod config;
+pub mod connect;
 pub mod error;
 pub mod eth;
+pub mod history;
+pub mod libp2p;
+pub mod ln;
+#[cfg(feature = "test-utils")]
+pub mod mock;
+pub mod record;
+pub mod replay;
+pub mod resolver;
+pub mod watch;
 
 /// Perform a P2P handshake with a peer for each node in the network
 pub async fn handshake(config: Config) -> Result<(), eyre::ErrReport> {
-    let tasks: Vec<JoinHandle<Result<IpAddr, P2PError>>> = match config.commands {
-        Commands::Eth { nodes_addrs } => nodes_addrs
-            .into_iter()
-            .map(|node| {
-                tokio::spawn(
-                    eth::handshake(eth::Config {
-                        timeout: config.timeout,
-                        peer: node.to_owned(),
-                    })
-                    .map_err(move |err| {
-                        P2PError::P2PHandshakeError(error::P2PHandshake::new(
-                            err,
-                            node.address.clone().to_string(),
-                        ))
-                    }),
-                )
+    let connector = Connector {
+        proxy: config.proxy.clone(),
+        bind_addr: config.bind_addr,
+        interface: config.interface.clone(),
+    };
+    if let Commands::Btc {
+        chain,
+        user_agent,
+        seed_services,
+        command: Some(BtcCommands::Crawl(args)),
+        ..
+    } = &config.commands
+    {
+        let profile = chain.profile();
+        return btc_crawl(
+            config.timeout,
+            user_agent
+                .as_deref()
+                .unwrap_or(profile.user_agent)
+                .to_string(),
+            profile,
+            *seed_services,
+            connector,
+            args.clone(),
+        )
+        .await;
+    }
+
+    if let Commands::Replay(args) = &config.commands {
+        return replay_sessions(config.timeout, args).await;
+    }
+
+    if let Commands::History(args) = &config.commands {
+        let db = config
+            .db
+            .as_deref()
+            .ok_or_else(|| eyre::eyre!("the history subcommand requires --db"))?;
+        return show_history(db, args);
+    }
+    let mut history = config.db.as_deref().map(History::open).transpose()?;
+
+    if let Commands::Serve(args) = &config.commands {
+        return api::serve(&config, connector, history, args).await;
+    }
+
+    if let Some(interval) = config.interval {
+        return watch(&config, &connector, history, interval).await;
+    }
+
+    let reports = run_handshakes(&config, &connector).await?;
+    for report in &reports {
+        match &report.result {
+            Ok(outcome) => info!("{}", outcome),
+            Err(err) => error!("{}", err),
+        }
+    }
+    if let Some(history) = &mut history {
+        history.record(&reports)?;
+    }
+    Ok(())
+}
+
+/// Print the uptime and the latency of the recorded peers
+fn show_history(db: &Path, args: &HistoryArgs) -> Result<(), eyre::ErrReport> {
+    let history = History::open(db)?;
+    let peers = history.peers(&HistoryQuery {
+        target: args.target.clone(),
+        since: args
+            .since
+            .map(|since| {
+                SystemTime::now().checked_sub(since).ok_or_else(|| {
+                    eyre::eyre!(
+                        "--since {} goes back too far",
+                        humantime::format_duration(since)
+                    )
+                })
             })
-            .collect(),
+            .transpose()?,
+        bucket: args.bucket,
+    })?;
+    for peer in &peers {
+        info!("{}", peer);
+    }
+    info!("{} peers in the history", peers.len());
+    Ok(())
+}
+
+/// Replay the recorded sessions against the client and compare the results with the recorded ones
+async fn replay_sessions(timeout: u64, args: &ReplayArgs) -> Result<(), eyre::ErrReport> {
+    for path in &args.sessions {
+        let session = Session::load(path)?;
+        let recorded = session.error.as_deref().unwrap_or("successful");
+        match replay::replay(&session, timeout).await {
+            Ok(_) => info!(
+                "[replayed] [{}] ({}) [successful] recorded: {}",
+                session.target, session.protocol, recorded
+            ),
+            Err(err) => error!(
+                "[replayed] [{}] ({}) [failed] error: {} recorded: {}",
+                session.target,
+                session.protocol,
+                err.message(),
+                recorded
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Re-run the handshakes every `interval`, only logging the peers going up or down
+async fn watch(
+    config: &Config,
+    connector: &Connector,
+    mut history: Option<History>,
+    interval: Duration,
+) -> Result<(), eyre::ErrReport> {
+    let mut monitor = Monitor::default();
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => (),
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+
+        // A failing round, e.g. on a resolver error, must not stop the monitor
+        let reports = match run_handshakes(config, connector).await {
+            Ok(reports) => reports,
+            Err(err) => {
+                error!("handshake round failed, skipping it: {}", err);
+                continue;
+            }
+        };
+        debug!("handshake round finished: {} peers", reports.len());
+        if let Some(history) = &mut history {
+            if let Err(err) = history.record(&reports) {
+                error!("failed to record the handshake round: {}", err);
+            }
+        }
+        for report in reports {
+            telemetry::peer_up(&report.target, report.result.is_ok());
+            let transition = match report.result {
+                Ok(_) => monitor.record_up(&report.target, report.latency, report.timestamp),
+                Err(err) => monitor.record_down(&report.target, err.message()),
+            };
+            match transition {
+                Some(transition @ Transition::Up { .. }) => info!("{}", transition),
+                Some(transition @ Transition::Down { .. }) => warn!("{}", transition),
+                None => (),
+            }
+        }
+    }
+}
+
+/// Perform the handshake with every target of the command and wait for all of them to complete
+async fn run_handshakes(
+    config: &Config,
+    connector: &Connector,
+) -> Result<Vec<HandshakeReport>, eyre::ErrReport> {
+    let tasks: Vec<JoinHandle<HandshakeReport>> = match &config.commands {
+        Commands::Eth {
+            command: Some(EthCommands::Crawl(args)),
+            ..
+        } => eth_handshakes(
+            eth_crawl(config.timeout, args.clone()).await?,
+            config.timeout,
+            connector,
+            config.record.as_deref(),
+        ),
+        Commands::Eth {
+            nodes_addrs,
+            command: None,
+        } => {
+            let resolver = TokioAsyncResolver::tokio_from_system_conf()?;
+            let peers = eth::target::resolve_targets(nodes_addrs.clone(), &resolver).await;
+            eth_handshakes(peers, config.timeout, connector, config.record.as_deref())
+        }
+        Commands::Btc {
+            command: Some(BtcCommands::Crawl(_)),
+            ..
+        }
+        | Commands::History(_)
+        | Commands::Replay(_)
+        | Commands::Serve(_) => {
+            return Err(eyre::eyre!("the command does not perform a handshake set"))
+        }
         Commands::Btc {
             nodes_addrs,
+            chain,
             user_agent,
-        } => nodes_addrs
-            .into_iter()
-            .map(|node_address| {
-                tokio::spawn(
-                    btc::handshake(btc::Config {
-                        timeout: config.timeout,
-                        node_address,
-                        user_agent: user_agent.clone(),
-                    })
-                    .map_err(move |err| {
-                        P2PError::P2PHandshakeError(error::P2PHandshake::new(
-                            err,
-                            node_address.to_string(),
-                        ))
-                    }),
-                )
-            })
-            .collect(),
+            seed_services,
+            command: None,
+        } => {
+            chain_handshakes(
+                config,
+                connector,
+                nodes_addrs,
+                *seed_services,
+                user_agent.as_deref(),
+                chain.profile(),
+            )
+            .await?
+        }
+        Commands::Zec {
+            nodes_addrs,
+            user_agent,
+        } => {
+            chain_handshakes(
+                config,
+                connector,
+                nodes_addrs,
+                None,
+                user_agent.as_deref(),
+                Chain::Zcash.profile(),
+            )
+            .await?
+        }
+        Commands::Bch {
+            nodes_addrs,
+            user_agent,
+            seed_services,
+        } => {
+            chain_handshakes(
+                config,
+                connector,
+                nodes_addrs,
+                *seed_services,
+                user_agent.as_deref(),
+                Chain::BitcoinCash.profile(),
+            )
+            .await?
+        }
+        Commands::Libp2p { nodes_addrs } => {
+            libp2p_handshakes(nodes_addrs.clone(), config.timeout, connector)
+        }
+        Commands::Beacon {
+            nodes_addrs,
+            network,
+            fork_digest,
+        } => beacon_handshakes(
+            nodes_addrs.clone(),
+            config.timeout,
+            *network,
+            *fork_digest,
+            connector,
+        ),
+        Commands::Ln {
+            nodes_addrs,
+            network,
+        } => ln_handshakes(nodes_addrs.clone(), config.timeout, *network, connector),
     };
 
     // Wait for all the tasks to complete
+    let mut reports = Vec::with_capacity(tasks.len());
     for task in tasks {
-        match task.await? {
-            Ok(addr) => info!("[successful] [{:?}] ", addr),
-            Err(err) => error!("{}", err),
+        reports.push(task.await?);
+    }
+    Ok(reports)
+}
+
+/// Resolve the targets of a chain speaking the bitcoin protocol and spawn their handshakes
+async fn chain_handshakes(
+    config: &Config,
+    connector: &Connector,
+    nodes_addrs: &[btc::target::Target],
+    seed_services: Option<u64>,
+    user_agent: Option<&str>,
+    profile: Profile,
+) -> Result<Vec<JoinHandle<HandshakeReport>>, eyre::ErrReport> {
+    let resolver = TokioAsyncResolver::tokio_from_system_conf()?;
+    let destinations = btc::target::resolve_targets(
+        nodes_addrs.to_vec(),
+        seed_services,
+        profile.default_port,
+        &resolver,
+    )
+    .await;
+    Ok(btc_handshakes(
+        destinations,
+        config.timeout,
+        user_agent.unwrap_or(profile.user_agent),
+        profile,
+        connector,
+        config.record.as_deref(),
+    ))
+}
+
+/// Spawn a handshake task measuring its latency
+fn spawn_handshake<F, T>(
+    protocol: &'static str,
+    target: String,
+    extras: Option<String>,
+    handshake: F,
+) -> JoinHandle<HandshakeReport>
+where
+    F: Future<Output = Result<T, P2PError>> + Send + 'static,
+    T: Into<Handshaked>,
+{
+    let metrics = HandshakeMetrics::new(protocol);
+    tokio::spawn(async move {
+        let _in_flight = metrics.attempt();
+        let timestamp = SystemTime::now();
+        let started = Instant::now();
+        let (result, phases) = collect_phases(handshake).await;
+        let latency = started.elapsed();
+        let result = match result {
+            Ok(handshaked) => {
+                metrics.success(latency);
+                let Handshaked {
+                    endpoints,
+                    peer_info,
+                } = handshaked.into();
+                Ok(HandshakeOutcome {
+                    addr: endpoints.remote,
+                    local: endpoints.local,
+                    extras: match (extras, peer_info) {
+                        (Some(extras), Some(peer_info)) => {
+                            Some(format!("{} {}", extras, peer_info))
+                        }
+                        (extras, peer_info) => extras.or(peer_info),
+                    },
+                })
+            }
+            Err(err) => {
+                metrics.failure(err.kind());
+                Err(P2PError::P2PHandshakeError(error::P2PHandshake::new(
+                    err,
+                    target.clone(),
+                )))
+            }
+        };
+        HandshakeReport {
+            protocol,
+            target,
+            timestamp,
+            latency,
+            phases,
+            result,
         }
+    })
+}
+
+/// The connection a handshake completed on, and what the peer told about itself
+#[derive(Debug)]
+pub struct Handshaked {
+    pub endpoints: Endpoints,
+    pub peer_info: Option<String>,
+}
+
+impl From<Endpoints> for Handshaked {
+    fn from(endpoints: Endpoints) -> Self {
+        Self {
+            endpoints,
+            peer_info: None,
+        }
+    }
+}
+
+impl From<btc::Peer> for Handshaked {
+    fn from(peer: btc::Peer) -> Self {
+        Self {
+            peer_info: Some(peer.info()),
+            endpoints: peer.endpoints,
+        }
+    }
+}
+
+/// A handshake attempt with a single target
+#[derive(Debug)]
+pub struct HandshakeReport {
+    pub protocol: &'static str,
+    /// The target as given, resolved or discovered
+    pub target: String,
+    /// When the attempt started
+    pub timestamp: SystemTime,
+    /// Time taken by the connection and the handshake, until it completed or failed
+    pub latency: Duration,
+    /// Duration of each phase the handshake went through, in order
+    pub phases: Vec<(&'static str, Duration)>,
+    pub result: Result<HandshakeOutcome, P2PError>,
+}
+
+/// Summary of a successful handshake
+#[derive(Debug)]
+pub struct HandshakeOutcome {
+    /// The address which completed the handshake, or the destination when using a proxy
+    pub addr: Destination,
+    /// The local address the connection was made from
+    pub local: SocketAddr,
+    /// Extra information known about the peer, e.g. from its node record
+    pub extras: Option<String>,
+}
+
+impl Display for HandshakeOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.addr {
+            Destination::Addr(addr) => write!(f, "[successful] [{:?}] ", addr.ip())?,
+            destination => write!(f, "[successful] [{}] ", destination)?,
+        }
+        write!(f, "({}) local: {} ", self.addr.family(), self.local)?;
+        if let Some(extras) = &self.extras {
+            write!(f, "{}", extras)?;
+        }
+        Ok(())
+    }
+}
+
+/// Spawn a P2P handshake task for each node of a chain speaking the bitcoin protocol
+fn btc_handshakes(
+    destinations: Vec<Destination>,
+    timeout: u64,
+    user_agent: &str,
+    profile: Profile,
+    connector: &Connector,
+    record: Option<&Path>,
+) -> Vec<JoinHandle<HandshakeReport>> {
+    destinations
+        .into_iter()
+        .map(|node_address| {
+            let target = node_address.to_string();
+            let extras = match &node_address {
+                Destination::Host { host, .. } if connector.proxy.is_none() => {
+                    Some(format!("host: {}", host))
+                }
+                _ => None,
+            };
+            spawn_handshake(
+                profile.name,
+                target,
+                extras,
+                btc::handshake(btc::Config {
+                    timeout,
+                    node_address,
+                    user_agent: user_agent.to_string(),
+                    profile,
+                    connector: connector.clone(),
+                    record: record.map(PathBuf::from),
+                }),
+            )
+        })
+        .collect()
+}
+
+/// Spawn a libp2p handshake task for each multiaddr
+fn libp2p_handshakes(
+    addresses: Vec<Multiaddr>,
+    timeout: u64,
+    connector: &Connector,
+) -> Vec<JoinHandle<HandshakeReport>> {
+    addresses
+        .into_iter()
+        .map(|address| {
+            spawn_handshake(
+                "libp2p",
+                address.to_string(),
+                None,
+                libp2p::handshake(libp2p::Config {
+                    address,
+                    timeout,
+                    connector: connector.clone(),
+                }),
+            )
+        })
+        .collect()
+}
+
+/// Spawn a status exchange task for each beacon node
+fn beacon_handshakes(
+    addresses: Vec<Multiaddr>,
+    timeout: u64,
+    network: Network,
+    fork_digest: Option<ForkDigest>,
+    connector: &Connector,
+) -> Vec<JoinHandle<HandshakeReport>> {
+    addresses
+        .into_iter()
+        .map(|address| {
+            spawn_handshake(
+                "beacon",
+                address.to_string(),
+                None,
+                beacon::handshake(beacon::Config {
+                    address,
+                    timeout,
+                    connector: connector.clone(),
+                    network,
+                    fork_digest,
+                }),
+            )
+        })
+        .collect()
+}
+
+/// Spawn a lightning handshake task for each node
+fn ln_handshakes(
+    nodes: Vec<ln::target::Target>,
+    timeout: u64,
+    network: bitcoin::Network,
+    connector: &Connector,
+) -> Vec<JoinHandle<HandshakeReport>> {
+    nodes
+        .into_iter()
+        .map(|node| {
+            spawn_handshake(
+                "ln",
+                node.to_string(),
+                None,
+                ln::handshake(ln::Config {
+                    node,
+                    timeout,
+                    connector: connector.clone(),
+                    network,
+                }),
+            )
+        })
+        .collect()
+}
+
+/// Spawn a P2P handshake task for each ethereum peer
+fn eth_handshakes(
+    peers: Vec<Peer>,
+    timeout: u64,
+    connector: &Connector,
+    record: Option<&Path>,
+) -> Vec<JoinHandle<HandshakeReport>> {
+    peers
+        .into_iter()
+        .map(|peer| {
+            let extras = peer.extras();
+            let config = eth::Config {
+                timeout,
+                peer: peer.node,
+                host: peer.host,
+                connector: connector.clone(),
+                record: record.map(PathBuf::from),
+            };
+            spawn_handshake(
+                "eth",
+                config.destination().to_string(),
+                extras,
+                eth::handshake(config),
+            )
+        })
+        .collect()
+}
+
+/// Discover ethereum nodes over discv4 or discv5 starting from the bootnodes
+async fn eth_crawl(timeout: u64, args: EthCrawlArgs) -> Result<Vec<Peer>, eyre::ErrReport> {
+    let peers: Vec<Peer> = match args.discovery {
+        Discovery::V4 => {
+            let bootnodes = if args.bootnodes.is_empty() {
+                holesky_nodes()
+            } else {
+                args.bootnodes
+            };
+
+            eth::crawl::crawl(eth::crawl::CrawlConfig {
+                bootnodes,
+                timeout,
+                max_depth: args.max_depth,
+                max_nodes: args.max_nodes,
+                concurrency: args.concurrency,
+            })
+            .await
+            .into_iter()
+            .map(Peer::from)
+            .collect()
+        }
+        Discovery::V5 => {
+            let enrs = eth::discv5::crawl(eth::discv5::CrawlConfig {
+                bootnodes: args.bootnode_enrs,
+                timeout,
+                lookups: args.lookups,
+                max_nodes: args.max_nodes,
+            })
+            .await?;
+            info!("discv5 crawl found {} records", enrs.len());
+
+            // Only execution-layer nodes advertise an `eth` entry and accept RLPx connections
+            enrs.into_iter()
+                .filter_map(|enr| {
+                    let fork_id = eth::enr::fork_id(&enr)?;
+                    let peer = Peer::from_enr(enr)?;
+                    debug!(
+                        "[discovered] [{:?}] fork id: {:?}",
+                        peer.node.tcp_addr(),
+                        fork_id
+                    );
+                    Some(peer)
+                })
+                .collect()
+        }
+    };
+
+    info!("crawl finished: {} nodes discovered", peers.len());
+    Ok(peers)
+}
+
+/// Crawl the network of the chain from the seed addresses and report every reachable node
+async fn btc_crawl(
+    timeout: u64,
+    user_agent: String,
+    profile: Profile,
+    seed_services: Option<u64>,
+    connector: Connector,
+    args: BtcCrawlArgs,
+) -> Result<(), eyre::ErrReport> {
+    let resolver = TokioAsyncResolver::tokio_from_system_conf()?;
+    let port = profile.default_port;
+    // Hostnames are left to the proxy when there is one
+    let seeds = match connector.proxy {
+        Some(_) => btc::target::resolve_targets(args.seeds, seed_services, port, &resolver).await,
+        None => btc::target::resolve_addrs(args.seeds, seed_services, port, &resolver)
+            .await
+            .into_iter()
+            .map(Destination::Addr)
+            .collect(),
+    };
+    let nodes = btc::crawl::crawl(btc::crawl::CrawlConfig {
+        seeds,
+        timeout,
+        addr_timeout: args.addr_timeout,
+        user_agent,
+        profile,
+        max_depth: args.max_depth,
+        max_nodes: args.max_nodes,
+        concurrency: args.concurrency,
+        connector,
+    })
+    .await;
+
+    for node in &nodes {
+        info!(
+            "[reachable] [{}] local: {} version: {} services: {} user agent: {} height: {} depth: {}",
+            node.address,
+            node.local,
+            node.version,
+            node.services,
+            node.user_agent,
+            node.start_height,
+            node.depth
+        );
     }
+    info!("crawl finished: {} reachable nodes", nodes.len());
     Ok(())
 }
@@ -0,0 +1,625 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use hickory_resolver::TokioAsyncResolver;
+use libp2p::Multiaddr;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, UNIX_EPOCH},
+};
+use tokio::{sync::Semaphore, time::Instant};
+use tracing::{error, info, warn};
+
+use crate::p2p::{
+    beacon::network::Network,
+    beacon_handshakes,
+    btc::{
+        self,
+        chain::{Chain, Profile},
+    },
+    btc_handshakes,
+    commands::ServeArgs,
+    config::Config,
+    connect::Connector,
+    eth, eth_handshakes,
+    history::History,
+    libp2p_handshakes, ln, ln_handshakes, HandshakeReport,
+};
+
+/// Protocol of the handshakes of a request
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    Btc,
+    Ltc,
+    Doge,
+    Zec,
+    Bch,
+    Eth,
+    Libp2p,
+    Beacon,
+    Ln,
+}
+
+impl Protocol {
+    fn name(self) -> &'static str {
+        match self {
+            Protocol::Btc => "btc",
+            Protocol::Ltc => "ltc",
+            Protocol::Doge => "doge",
+            Protocol::Zec => "zec",
+            Protocol::Bch => "bch",
+            Protocol::Eth => "eth",
+            Protocol::Libp2p => "libp2p",
+            Protocol::Beacon => "beacon",
+            Protocol::Ln => "ln",
+        }
+    }
+}
+
+/// Body of `POST /handshake`
+#[derive(Clone, Debug, Deserialize)]
+pub struct HandshakeRequest {
+    pub protocol: Protocol,
+    /// Targets in the format of the corresponding subcommand, e.g. `enode://` URLs for eth
+    pub targets: Vec<String>,
+    /// Handshake operation maximum time (in ms), the `--timeout` of the server by default
+    pub timeout: Option<u64>,
+    /// User agent of the bitcoin handshakes, the one of the chain reference client by default
+    pub user_agent: Option<String>,
+    /// Network of the beacon or lightning handshakes, as `--network` of their subcommand
+    pub network: Option<RequestNetwork>,
+}
+
+/// Network of a request, told apart by name
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum RequestNetwork {
+    /// Mainnet by default
+    Beacon(Network),
+    /// Bitcoin by default, announced in the init messages
+    Ln(bitcoin::Network),
+}
+
+/// Reply to `POST /handshake`
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+pub struct Submitted {
+    pub id: u64,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Running,
+    Done,
+    /// The request timeout elapsed, only the completed handshakes are reported
+    TimedOut,
+}
+
+/// Reply to `GET /results/{id}`
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct RequestResults {
+    pub id: u64,
+    pub protocol: Protocol,
+    pub status: Status,
+    pub results: Vec<HandshakeResult>,
+}
+
+/// A handshake attempt with a single target
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct HandshakeResult {
+    pub target: String,
+    /// Unix time the attempt started at (in ms)
+    pub timestamp: u64,
+    pub latency_ms: f64,
+    pub success: bool,
+    pub remote: Option<String>,
+    pub local: Option<String>,
+    pub extras: Option<String>,
+    pub error_kind: Option<&'static str>,
+    pub error: Option<String>,
+}
+
+impl From<&HandshakeReport> for HandshakeResult {
+    fn from(report: &HandshakeReport) -> Self {
+        let (outcome, err) = match &report.result {
+            Ok(outcome) => (Some(outcome), None),
+            Err(err) => (None, Some(err)),
+        };
+        Self {
+            target: report.target.clone(),
+            timestamp: report
+                .timestamp
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_millis() as u64),
+            latency_ms: report.latency.as_secs_f64() * 1000.0,
+            success: outcome.is_some(),
+            remote: outcome.map(|outcome| outcome.addr.to_string()),
+            local: outcome.map(|outcome| outcome.local.to_string()),
+            extras: outcome.and_then(|outcome| outcome.extras.clone()),
+            error_kind: err.map(|err| err.kind()),
+            error: err.map(|err| err.message()),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum ApiError {
+    #[error("no target given")]
+    NoTarget,
+    #[error("{0} targets given, at most {1} are allowed")]
+    TooManyTargets(usize, usize),
+    #[error("invalid target {0}: {1}")]
+    InvalidTarget(String, String),
+    #[error("{0} handshakes can't be performed on the {1} network")]
+    WrongNetwork(&'static str, String),
+    #[error("too many requests running, retry later")]
+    Busy,
+    #[error("unknown request {0}")]
+    UnknownRequest(u64),
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            ApiError::NoTarget
+            | ApiError::TooManyTargets(..)
+            | ApiError::InvalidTarget(..)
+            | ApiError::WrongNetwork(..) => StatusCode::BAD_REQUEST,
+            ApiError::Busy => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::UnknownRequest(_) => StatusCode::NOT_FOUND,
+        };
+        let body = ErrorBody {
+            error: self.to_string(),
+        };
+        (status, Json(body)).into_response()
+    }
+}
+
+/// The parsed targets of a request
+enum Targets {
+    Btc(Profile, Vec<btc::target::Target>),
+    Eth(Vec<eth::target::Target>),
+    Libp2p(Vec<Multiaddr>),
+    Beacon(Network, Vec<Multiaddr>),
+    Ln(bitcoin::Network, Vec<ln::target::Target>),
+}
+
+impl Targets {
+    fn parse(request: &HandshakeRequest) -> Result<Self, ApiError> {
+        let targets = &request.targets;
+        let btc = |chain: Chain| -> Result<Self, ApiError> {
+            Ok(Targets::Btc(chain.profile(), parse_all(targets)?))
+        };
+        match request.protocol {
+            Protocol::Btc => btc(Chain::Bitcoin),
+            Protocol::Ltc => btc(Chain::Litecoin),
+            Protocol::Doge => btc(Chain::Dogecoin),
+            Protocol::Zec => btc(Chain::Zcash),
+            Protocol::Bch => btc(Chain::BitcoinCash),
+            Protocol::Eth => Ok(Targets::Eth(parse_all(targets)?)),
+            Protocol::Libp2p => Ok(Targets::Libp2p(parse_all(targets)?)),
+            Protocol::Beacon => {
+                let network = match request.network {
+                    None => Network::default(),
+                    Some(RequestNetwork::Beacon(network)) => network,
+                    Some(RequestNetwork::Ln(network)) => {
+                        return Err(ApiError::WrongNetwork("beacon", network.to_string()))
+                    }
+                };
+                Ok(Targets::Beacon(network, parse_all(targets)?))
+            }
+            Protocol::Ln => {
+                let network = match request.network {
+                    None => bitcoin::Network::Bitcoin,
+                    Some(RequestNetwork::Ln(network)) => network,
+                    Some(RequestNetwork::Beacon(network)) => {
+                        return Err(ApiError::WrongNetwork("ln", network.to_string()))
+                    }
+                };
+                Ok(Targets::Ln(network, parse_all(targets)?))
+            }
+        }
+    }
+}
+
+fn parse_all<T>(targets: &[String]) -> Result<Vec<T>, ApiError>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    targets
+        .iter()
+        .map(|target| {
+            target
+                .parse()
+                .map_err(|err: T::Err| ApiError::InvalidTarget(target.clone(), err.to_string()))
+        })
+        .collect()
+}
+
+/// The results of the requests, oldest first
+#[derive(Default)]
+struct Requests {
+    next_id: u64,
+    results: BTreeMap<u64, RequestResults>,
+}
+
+struct ApiState {
+    connector: Connector,
+    timeout: u64,
+    record: Option<PathBuf>,
+    resolver: TokioAsyncResolver,
+    max_targets: usize,
+    request_timeout: Duration,
+    max_results: usize,
+    /// One permit per request allowed to run at once
+    permits: Arc<Semaphore>,
+    requests: Mutex<Requests>,
+    /// Written from a blocking task, not to stall the runtime during the SQLite writes
+    history: Option<Arc<Mutex<History>>>,
+}
+
+impl ApiState {
+    fn new(
+        connector: Connector,
+        timeout: u64,
+        record: Option<PathBuf>,
+        resolver: TokioAsyncResolver,
+        history: Option<History>,
+        args: &ServeArgs,
+    ) -> Self {
+        Self {
+            connector,
+            timeout,
+            record,
+            resolver,
+            max_targets: args.max_targets,
+            request_timeout: args.request_timeout,
+            max_results: args.max_results,
+            permits: Arc::new(Semaphore::new(args.max_requests)),
+            requests: Mutex::default(),
+            history: history.map(|history| Arc::new(Mutex::new(history))),
+        }
+    }
+
+    /// Register a new running request, dropping the oldest results beyond `max_results`
+    fn insert(&self, protocol: Protocol) -> u64 {
+        let mut requests = self.requests.lock().unwrap();
+        let id = requests.next_id;
+        requests.next_id += 1;
+        while requests.results.len() >= self.max_results.max(1) {
+            requests.results.pop_first();
+        }
+        requests.results.insert(
+            id,
+            RequestResults {
+                id,
+                protocol,
+                status: Status::Running,
+                results: Vec::new(),
+            },
+        );
+        id
+    }
+
+    async fn finish(&self, id: u64, status: Status, reports: Vec<HandshakeReport>) {
+        if let Some(request) = self.requests.lock().unwrap().results.get_mut(&id) {
+            request.status = status;
+            request.results = reports.iter().map(HandshakeResult::from).collect();
+        }
+        if let Some(history) = self.history.clone() {
+            let recorded =
+                tokio::task::spawn_blocking(move || history.lock().unwrap().record(&reports)).await;
+            match recorded {
+                Ok(Ok(())) => (),
+                Ok(Err(err)) => {
+                    error!("failed to record the handshakes of request {}: {}", id, err)
+                }
+                Err(err) => error!("history task of request {} failed: {}", id, err),
+            }
+        }
+    }
+
+    /// The handshake timeout of a request, bounded by the server timeout and the request timeout
+    fn handshake_timeout(&self, requested: Option<u64>) -> u64 {
+        requested
+            .unwrap_or(self.timeout)
+            .min(self.timeout)
+            .min(self.request_timeout.as_millis() as u64)
+    }
+
+    /// Keep the first `max_targets` targets, once the seeds and the node lists are expanded
+    fn limit<T>(&self, mut targets: Vec<T>) -> Vec<T> {
+        if targets.len() > self.max_targets {
+            warn!(
+                "dropping {} of the {} resolved targets beyond the limit",
+                targets.len() - self.max_targets,
+                targets.len()
+            );
+            targets.truncate(self.max_targets);
+        }
+        targets
+    }
+
+    /// Perform the handshakes of a request until they complete or the request timeout elapses
+    async fn run(
+        &self,
+        targets: Targets,
+        timeout: u64,
+        user_agent: Option<&str>,
+    ) -> (Status, Vec<HandshakeReport>) {
+        let deadline = Instant::now() + self.request_timeout;
+        let spawn = async {
+            match targets {
+                Targets::Btc(profile, targets) => {
+                    let destinations = btc::target::resolve_targets(
+                        targets,
+                        None,
+                        profile.default_port,
+                        &self.resolver,
+                    )
+                    .await;
+                    btc_handshakes(
+                        self.limit(destinations),
+                        timeout,
+                        user_agent.unwrap_or(profile.user_agent),
+                        profile,
+                        &self.connector,
+                        self.record.as_deref(),
+                    )
+                }
+                Targets::Eth(targets) => {
+                    let peers = eth::target::resolve_targets(targets, &self.resolver).await;
+                    eth_handshakes(
+                        self.limit(peers),
+                        timeout,
+                        &self.connector,
+                        self.record.as_deref(),
+                    )
+                }
+                Targets::Libp2p(addresses) => {
+                    libp2p_handshakes(addresses, timeout, &self.connector)
+                }
+                Targets::Beacon(network, addresses) => {
+                    beacon_handshakes(addresses, timeout, network, None, &self.connector)
+                }
+                Targets::Ln(network, nodes) => {
+                    ln_handshakes(nodes, timeout, network, &self.connector)
+                }
+            }
+        };
+        let Ok(tasks) = tokio::time::timeout_at(deadline, spawn).await else {
+            return (Status::TimedOut, Vec::new());
+        };
+
+        let mut status = Status::Done;
+        let mut reports = Vec::with_capacity(tasks.len());
+        for mut task in tasks {
+            match tokio::time::timeout_at(deadline, &mut task).await {
+                Ok(Ok(report)) => reports.push(report),
+                Ok(Err(err)) => error!("handshake task failed: {}", err),
+                Err(_) => {
+                    task.abort();
+                    status = Status::TimedOut;
+                }
+            }
+        }
+        (status, reports)
+    }
+}
+
+/// Start the handshakes of a request in the background
+async fn submit(
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<HandshakeRequest>,
+) -> Result<(StatusCode, Json<Submitted>), ApiError> {
+    if request.targets.is_empty() {
+        return Err(ApiError::NoTarget);
+    }
+    if request.targets.len() > state.max_targets {
+        return Err(ApiError::TooManyTargets(
+            request.targets.len(),
+            state.max_targets,
+        ));
+    }
+    let targets = Targets::parse(&request)?;
+    let permit = state
+        .permits
+        .clone()
+        .try_acquire_owned()
+        .map_err(|_| ApiError::Busy)?;
+
+    let id = state.insert(request.protocol);
+    info!(
+        "request {}: {} handshake with {} targets",
+        id,
+        request.protocol.name(),
+        request.targets.len()
+    );
+    tokio::spawn(async move {
+        let _permit = permit;
+        let timeout = state.handshake_timeout(request.timeout);
+        let (status, reports) = state
+            .run(targets, timeout, request.user_agent.as_deref())
+            .await;
+        state.finish(id, status, reports).await;
+    });
+    Ok((StatusCode::ACCEPTED, Json(Submitted { id })))
+}
+
+/// The status and the results of a request
+async fn results(
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<u64>,
+) -> Result<Json<RequestResults>, ApiError> {
+    state
+        .requests
+        .lock()
+        .unwrap()
+        .results
+        .get(&id)
+        .cloned()
+        .map(Json)
+        .ok_or(ApiError::UnknownRequest(id))
+}
+
+fn router(state: Arc<ApiState>) -> Router {
+    Router::new()
+        .route("/handshake", post(submit))
+        .route("/results/:id", get(results))
+        .with_state(state)
+}
+
+/// Serve the HTTP API until interrupted
+pub async fn serve(
+    config: &Config,
+    connector: Connector,
+    history: Option<History>,
+    args: &ServeArgs,
+) -> Result<(), eyre::ErrReport> {
+    let resolver = TokioAsyncResolver::tokio_from_system_conf()?;
+    let state = Arc::new(ApiState::new(
+        connector,
+        config.timeout,
+        config.record.clone(),
+        resolver,
+        history,
+        args,
+    ));
+
+    let server = axum::Server::try_bind(&args.listen)?.serve(router(state).into_make_service());
+    info!("serving the HTTP API on {}", server.local_addr());
+    server
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hickory_resolver::config::{ResolverConfig, ResolverOpts};
+    use tokio::net::TcpListener;
+
+    fn state(max_requests: usize) -> Arc<ApiState> {
+        let args = ServeArgs {
+            listen: "127.0.0.1:0".parse().unwrap(),
+            max_requests,
+            max_targets: 2,
+            request_timeout: Duration::from_secs(5),
+            max_results: 10,
+        };
+        let resolver =
+            TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default());
+        Arc::new(ApiState::new(
+            Connector::default(),
+            1000,
+            None,
+            resolver,
+            None,
+            &args,
+        ))
+    }
+
+    fn request(targets: &[&str]) -> Json<HandshakeRequest> {
+        Json(HandshakeRequest {
+            protocol: Protocol::Btc,
+            targets: targets.iter().map(|target| target.to_string()).collect(),
+            timeout: None,
+            user_agent: None,
+            network: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_invalid_requests() {
+        let state = state(1);
+
+        // Verify that the invalid requests are rejected before running anything
+        let err = submit(State(state.clone()), request(&[]))
+            .await
+            .unwrap_err();
+        assert_eq!(err, ApiError::NoTarget);
+        let err = submit(State(state.clone()), request(&["a:1", "b:1", "c:1"]))
+            .await
+            .unwrap_err();
+        assert_eq!(err, ApiError::TooManyTargets(3, 2));
+        let err = submit(State(state.clone()), request(&["not a target"]))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::InvalidTarget(..)));
+        let ln: HandshakeRequest = serde_json::from_str(
+            r#"{"protocol": "ln", "targets": ["not a target"], "network": "sepolia"}"#,
+        )
+        .unwrap();
+        assert_eq!(ln.network, Some(RequestNetwork::Beacon(Network::Sepolia)));
+        let err = submit(State(state.clone()), Json(ln)).await.unwrap_err();
+        assert!(matches!(err, ApiError::WrongNetwork("ln", _)));
+        let err = results(State(state), Path(0)).await.unwrap_err();
+        assert_eq!(err, ApiError::UnknownRequest(0));
+    }
+
+    #[tokio::test]
+    async fn test_request_limits() {
+        let state = state(1);
+
+        // Verify that the handshake timeout can only be lowered, down to the request timeout
+        assert_eq!(state.handshake_timeout(None), 1000);
+        assert_eq!(state.handshake_timeout(Some(200)), 200);
+        assert_eq!(state.handshake_timeout(Some(60_000)), 1000);
+
+        // Verify that the expanded targets are limited as well
+        assert_eq!(state.limit(vec![1, 2, 3]), vec![1, 2]);
+        assert_eq!(state.limit(vec![1]), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test_request_results() {
+        let state = state(1);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed = listener.local_addr().unwrap().to_string();
+        drop(listener);
+
+        let (status, Json(submitted)) = submit(State(state.clone()), request(&[&closed]))
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::ACCEPTED);
+
+        // Verify that only one request runs at once
+        let err = submit(State(state.clone()), request(&[&closed]))
+            .await
+            .unwrap_err();
+        assert_eq!(err, ApiError::Busy);
+
+        // Verify that the failed handshake is reported once the request is done
+        let request = loop {
+            let Json(request) = results(State(state.clone()), Path(submitted.id))
+                .await
+                .unwrap();
+            if request.status != Status::Running {
+                break request;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert_eq!(request.status, Status::Done);
+        assert_eq!(request.results.len(), 1);
+        assert_eq!(request.results[0].target, closed);
+        assert!(!request.results[0].success);
+        assert_eq!(request.results[0].error_kind, Some("io"));
+    }
+}
@@ -0,0 +1,108 @@
+use data_encoding::HEXLOWER;
+use libp2p::Multiaddr;
+use measure_time::info_time;
+use std::time::{Duration, Instant};
+use tracing::{info, instrument, warn};
+
+use crate::{
+    p2p::{
+        beacon::{
+            network::{ForkDigest, Network},
+            rpc::Status,
+        },
+        connect::{Connector, Endpoints},
+        error::{BeaconError, P2PError},
+        libp2p::Session,
+    },
+    telemetry::HandshakeMetrics,
+};
+
+#[cfg(feature = "test-utils")]
+pub mod mock;
+pub mod network;
+pub mod rpc;
+
+#[derive(Debug)]
+pub struct Config {
+    /// `/ip4|ip6|dns/<host>/tcp/<port>`, with the expected `/p2p/<peer id>` of the node
+    pub address: Multiaddr,
+    pub timeout: u64,
+    pub connector: Connector,
+    pub network: Network,
+    /// Expected fork digest of the node, instead of the forks of the network
+    pub fork_digest: Option<ForkDigest>,
+}
+
+/// Exchange the status with a beacon node and verify it is on the expected network
+#[instrument(level = "trace", skip_all, fields(peer=&*format!("{}", config.address)))]
+pub async fn handshake(config: Config) -> Result<Endpoints, P2PError> {
+    info_time!("[{}] Perform a beacon handshake", config.address);
+
+    let metrics = HandshakeMetrics::new("beacon");
+    let mut session =
+        Session::connect(&config.address, config.timeout, &config.connector, &metrics).await?;
+    let timeout = Duration::from_millis(config.timeout);
+
+    // Announce a node at genesis, the peer answers with its own status
+    let started = Instant::now();
+    let ours = Status {
+        fork_digest: config
+            .fork_digest
+            .unwrap_or_else(|| config.network.current_digest()),
+        ..Default::default()
+    };
+    let status = tokio::time::timeout(
+        timeout,
+        session.run(|stream| rpc::request_status(stream, &ours)),
+    )
+    .await??;
+    let fork = match config.fork_digest {
+        Some(digest) if digest == status.fork_digest => Some("custom"),
+        Some(_) => None,
+        None => config.network.fork(status.fork_digest),
+    }
+    .ok_or_else(|| BeaconError::ForkDigestMismatch {
+        digest: HEXLOWER.encode(&status.fork_digest),
+        network: config.network.to_string(),
+    })?;
+    metrics.phase("status", started.elapsed());
+
+    // The metadata is informative, the nodes may rate limit it
+    let started = Instant::now();
+    let metadata = tokio::time::timeout(timeout, session.run(rpc::request_metadata)).await;
+    metrics.phase("metadata", started.elapsed());
+    let metadata = match metadata {
+        Ok(Ok(metadata)) => Some(metadata),
+        Ok(Err(err)) => {
+            warn!("[{}] metadata request failed: {}", session.destination, err);
+            None
+        }
+        Err(_) => {
+            warn!("[{}] metadata request timed out", session.destination);
+            None
+        }
+    };
+
+    info!(
+        "[{}] peer id: {} fork: {} finalized epoch: {} finalized root: {} head slot: {}{}",
+        session.destination,
+        session.peer_id,
+        fork,
+        status.finalized_epoch,
+        HEXLOWER.encode(&status.finalized_root),
+        status.head_slot,
+        metadata
+            .map(|metadata| format!(
+                " seq number: {} attnets: {} syncnets: {:#04b}",
+                metadata.seq_number,
+                metadata
+                    .attnets
+                    .iter()
+                    .map(|byte| byte.count_ones())
+                    .sum::<u32>(),
+                metadata.syncnets
+            ))
+            .unwrap_or_default()
+    );
+    Ok(session.endpoints)
+}
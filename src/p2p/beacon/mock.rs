@@ -0,0 +1,121 @@
+use futures::{future::poll_fn, AsyncReadExt, AsyncWriteExt};
+use libp2p::{
+    core::{muxing::StreamMuxerExt, upgrade},
+    identity::Keypair,
+    noise, yamux, Multiaddr, PeerId,
+};
+use multistream_select::listener_select_proto;
+use std::{io, net::SocketAddr, sync::Mutex};
+use tokio::net::TcpStream;
+use tokio_util::compat::TokioAsyncReadCompatExt;
+
+use crate::p2p::{
+    beacon::{
+        network::Network,
+        rpc::{self, MetaData, Status, METADATA_PROTOCOL, STATUS_PROTOCOL},
+    },
+    error::{BeaconError, Libp2pError},
+    libp2p::{drive, mock::multiaddr},
+    mock::MockServer,
+};
+
+#[derive(Clone, Debug)]
+pub struct MockConfig {
+    pub key: Keypair,
+    /// Sent in reply to the status requests
+    pub status: Status,
+    /// Sent in reply to the metadata requests
+    pub metadata: MetaData,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        Self {
+            key: Keypair::generate_secp256k1(),
+            status: Status {
+                fork_digest: Network::Mainnet.current_digest(),
+                finalized_epoch: 1,
+                head_slot: 64,
+                ..Default::default()
+            },
+            metadata: MetaData::default(),
+        }
+    }
+}
+
+/// A scripted beacon node listening on a local port, stopped when dropped
+#[derive(Debug)]
+pub struct MockNode {
+    peer_id: PeerId,
+    server: MockServer<String>,
+}
+
+impl MockNode {
+    /// Listen on a random local port and answer every connection as configured
+    pub async fn spawn(config: MockConfig) -> io::Result<Self> {
+        let peer_id = config.key.public().to_peer_id();
+        let server = MockServer::spawn(move |stream, _, received| {
+            let config = config.clone();
+            async move { serve(stream, &config, &received).await }
+        })
+        .await?;
+        Ok(Self { peer_id, server })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.server.addr()
+    }
+
+    /// The multiaddr of the node, with its peer id
+    pub fn address(&self) -> Multiaddr {
+        multiaddr(self.addr(), self.peer_id)
+    }
+
+    /// Request protocols answered so far, over all the connections
+    pub fn received(&self) -> Vec<String> {
+        self.server.received()
+    }
+}
+
+/// Answer the requests of a single connection until the client closes it
+async fn serve(
+    stream: TcpStream,
+    config: &MockConfig,
+    received: &Mutex<Vec<String>>,
+) -> Result<(), BeaconError> {
+    let noise =
+        noise::Config::new(&config.key).map_err(|err| Libp2pError::Noise(err.to_string()))?;
+    let (_, stream) = upgrade::apply_inbound(stream.compat(), noise)
+        .await
+        .map_err(|err| Libp2pError::Noise(err.to_string()))?;
+    let mut muxer = upgrade::apply_inbound(stream, yamux::Config::default())
+        .await
+        .map_err(|err| Libp2pError::Yamux(err.to_string()))?;
+
+    loop {
+        let stream = poll_fn(|cx| muxer.poll_inbound_unpin(cx))
+            .await
+            .map_err(|err| Libp2pError::Yamux(err.to_string()))?;
+        let answer = async {
+            let (protocol, mut stream) =
+                listener_select_proto(stream, [STATUS_PROTOCOL, METADATA_PROTOCOL])
+                    .await
+                    .map_err(Libp2pError::from)?;
+            received.lock().unwrap().push(protocol.to_string());
+
+            // The client closes its side once the request is sent
+            let mut request = Vec::new();
+            stream.read_to_end(&mut request).await?;
+            let ssz = match protocol {
+                STATUS_PROTOCOL => config.status.encode(),
+                _ => config.metadata.encode(),
+            };
+            let mut response = vec![0];
+            response.extend(rpc::encode_payload(&ssz)?);
+            stream.write_all(&response).await?;
+            stream.close().await?;
+            Ok::<_, BeaconError>(())
+        };
+        drive(&mut muxer, answer).await?;
+    }
+}
@@ -0,0 +1,162 @@
+use clap::ValueEnum;
+use data_encoding::HEXLOWER;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+/// The first bytes of the fork data root, identifying a fork of a network
+pub type ForkDigest = [u8; 4];
+
+/// A beacon chain network, by its fork schedule
+#[derive(ValueEnum, Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    #[default]
+    Mainnet,
+    Sepolia,
+}
+
+/// A fork of a network, or a blob parameter only (BPO) fork changing its digest alone
+#[derive(Clone, Copy, Debug)]
+struct Fork {
+    name: &'static str,
+    version: [u8; 4],
+    /// The blob parameters mixed into the digest from Fulu on (EIP-7892): the epoch they apply
+    /// from and the maximum number of blobs per block
+    blobs: Option<(u64, u64)>,
+}
+
+const fn fork(name: &'static str, version: [u8; 4]) -> Fork {
+    Fork {
+        name,
+        version,
+        blobs: None,
+    }
+}
+
+const fn blob_fork(name: &'static str, version: [u8; 4], epoch: u64, max_blobs: u64) -> Fork {
+    Fork {
+        name,
+        version,
+        blobs: Some((epoch, max_blobs)),
+    }
+}
+
+impl Fork {
+    fn digest(&self, genesis_validators_root: [u8; 32]) -> ForkDigest {
+        let digest = fork_digest(self.version, genesis_validators_root);
+        match self.blobs {
+            Some((epoch, max_blobs)) => blob_digest(digest, epoch, max_blobs),
+            None => digest,
+        }
+    }
+}
+
+impl Network {
+    /// Forks of the network, oldest first. Fulu starts with the blob parameters of Electra.
+    fn forks(self) -> &'static [Fork] {
+        match self {
+            Network::Mainnet => &[
+                fork("phase0", [0x00, 0x00, 0x00, 0x00]),
+                fork("altair", [0x01, 0x00, 0x00, 0x00]),
+                fork("bellatrix", [0x02, 0x00, 0x00, 0x00]),
+                fork("capella", [0x03, 0x00, 0x00, 0x00]),
+                fork("deneb", [0x04, 0x00, 0x00, 0x00]),
+                fork("electra", [0x05, 0x00, 0x00, 0x00]),
+                blob_fork("fulu", [0x06, 0x00, 0x00, 0x00], 364_032, 9),
+                blob_fork("fulu-bpo1", [0x06, 0x00, 0x00, 0x00], 412_672, 15),
+                blob_fork("fulu-bpo2", [0x06, 0x00, 0x00, 0x00], 419_072, 21),
+            ],
+            Network::Sepolia => &[
+                fork("phase0", [0x90, 0x00, 0x00, 0x69]),
+                fork("altair", [0x90, 0x00, 0x00, 0x70]),
+                fork("bellatrix", [0x90, 0x00, 0x00, 0x71]),
+                fork("capella", [0x90, 0x00, 0x00, 0x72]),
+                fork("deneb", [0x90, 0x00, 0x00, 0x73]),
+                fork("electra", [0x90, 0x00, 0x00, 0x74]),
+                blob_fork("fulu", [0x90, 0x00, 0x00, 0x75], 222_464, 9),
+                blob_fork("fulu-bpo1", [0x90, 0x00, 0x00, 0x75], 274_176, 15),
+                blob_fork("fulu-bpo2", [0x90, 0x00, 0x00, 0x75], 275_456, 21),
+            ],
+        }
+    }
+
+    fn genesis_validators_root(self) -> [u8; 32] {
+        let root = match self {
+            Network::Mainnet => "4b363db94e286120d76eb905340fdd4e54bfe9f06bf33ff6cf5ad27f511bfe95",
+            Network::Sepolia => "d8ea171f3c94aea21ebc42a1ed61052acf3f9209c00e4efbaaddac09ed9b8078",
+        };
+        let mut bytes = [0; 32];
+        bytes.copy_from_slice(&HEXLOWER.decode(root.as_bytes()).unwrap());
+        bytes
+    }
+
+    /// The fork of the network a digest belongs to
+    pub fn fork(self, digest: ForkDigest) -> Option<&'static str> {
+        let root = self.genesis_validators_root();
+        self.forks()
+            .iter()
+            .find(|fork| fork.digest(root) == digest)
+            .map(|fork| fork.name)
+    }
+
+    /// The digest of the last fork of the network
+    pub fn current_digest(self) -> ForkDigest {
+        self.forks()[self.forks().len() - 1].digest(self.genesis_validators_root())
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = self.to_possible_value().expect("no skipped variant");
+        f.write_str(name.get_name())
+    }
+}
+
+/// The digest of the fork data (SSZ container of the fork version and the genesis validators
+/// root), as advertised by the nodes of the fork
+pub fn fork_digest(version: [u8; 4], genesis_validators_root: [u8; 32]) -> ForkDigest {
+    let mut leaves = [0; 64];
+    leaves[..4].copy_from_slice(&version);
+    leaves[32..].copy_from_slice(&genesis_validators_root);
+    let root = Sha256::digest(leaves);
+    [root[0], root[1], root[2], root[3]]
+}
+
+/// The digest of a fork from Fulu on, mixing in the blob parameters in effect (EIP-7892)
+fn blob_digest(digest: ForkDigest, epoch: u64, max_blobs: u64) -> ForkDigest {
+    let mut parameters = [0; 16];
+    parameters[..8].copy_from_slice(&epoch.to_le_bytes());
+    parameters[8..].copy_from_slice(&max_blobs.to_le_bytes());
+    let hash = Sha256::digest(parameters);
+    // Only the first bytes of the fork data root are kept, as in the digest
+    [
+        digest[0] ^ hash[0],
+        digest[1] ^ hash[1],
+        digest[2] ^ hash[2],
+        digest[3] ^ hash[3],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fork_digests() {
+        let network = Network::Mainnet;
+        let digests = [
+            "b5303f2a", "afcaaba0", "4a26c58b", "bba4da96", "6a95a1a9", "ad532ceb", "cc2c5cdb",
+            "cb0d1acc", "8c9f62fe",
+        ];
+        assert_eq!(network.forks().len(), digests.len());
+        for (fork, digest) in network.forks().iter().zip(digests) {
+            let expected = HEXLOWER.decode(digest.as_bytes()).unwrap();
+            let digest = fork.digest(network.genesis_validators_root());
+            assert_eq!(digest.as_slice(), expected.as_slice(), "{}", fork.name);
+            assert_eq!(network.fork(digest), Some(fork.name));
+        }
+        assert_eq!(Network::Sepolia.fork([0xb5, 0x30, 0x3f, 0x2a]), None);
+        assert_eq!(network.fork(network.current_digest()), Some("fulu-bpo2"));
+    }
+}
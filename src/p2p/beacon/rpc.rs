@@ -0,0 +1,203 @@
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use multistream_select::{dialer_select_proto, Version};
+use std::io::{Read, Write};
+use tracing::trace;
+
+use crate::p2p::{
+    beacon::network::ForkDigest,
+    error::{BeaconError, Libp2pError},
+    libp2p::identify::{read_varint, write_varint},
+};
+
+/// [`STATUS_PROTOCOL`] is the protocol id of the Status request.
+pub const STATUS_PROTOCOL: &str = "/eth2/beacon_chain/req/status/1/ssz_snappy";
+
+/// [`METADATA_PROTOCOL`] is the protocol id of the MetaData request.
+pub const METADATA_PROTOCOL: &str = "/eth2/beacon_chain/req/metadata/2/ssz_snappy";
+
+/// [`MAX_RESPONSE_SIZE`] is the largest response accepted, error messages included.
+const MAX_RESPONSE_SIZE: u64 = 1024;
+
+/// The head and finality of a node, exchanged when connecting
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Status {
+    pub fork_digest: ForkDigest,
+    pub finalized_root: [u8; 32],
+    pub finalized_epoch: u64,
+    pub head_root: [u8; 32],
+    pub head_slot: u64,
+}
+
+impl Status {
+    const SIZE: usize = 84;
+
+    /// Encode the message to SSZ
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::SIZE);
+        buf.extend_from_slice(&self.fork_digest);
+        buf.extend_from_slice(&self.finalized_root);
+        buf.extend_from_slice(&self.finalized_epoch.to_le_bytes());
+        buf.extend_from_slice(&self.head_root);
+        buf.extend_from_slice(&self.head_slot.to_le_bytes());
+        buf
+    }
+
+    /// Decode the SSZ encoded message
+    pub fn decode(data: &[u8]) -> Result<Self, BeaconError> {
+        if data.len() != Self::SIZE {
+            return Err(BeaconError::InvalidResponse("invalid status size"));
+        }
+        Ok(Self {
+            fork_digest: data[..4].try_into().unwrap(),
+            finalized_root: data[4..36].try_into().unwrap(),
+            finalized_epoch: u64::from_le_bytes(data[36..44].try_into().unwrap()),
+            head_root: data[44..76].try_into().unwrap(),
+            head_slot: u64::from_le_bytes(data[76..].try_into().unwrap()),
+        })
+    }
+}
+
+/// The subnets a node is subscribed to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MetaData {
+    pub seq_number: u64,
+    /// Bitvector of the attestation subnets
+    pub attnets: [u8; 8],
+    /// Bitvector of the sync committee subnets
+    pub syncnets: u8,
+}
+
+impl MetaData {
+    const SIZE: usize = 17;
+
+    /// Encode the message to SSZ
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::SIZE);
+        buf.extend_from_slice(&self.seq_number.to_le_bytes());
+        buf.extend_from_slice(&self.attnets);
+        buf.push(self.syncnets);
+        buf
+    }
+
+    /// Decode the SSZ encoded message
+    pub fn decode(data: &[u8]) -> Result<Self, BeaconError> {
+        if data.len() != Self::SIZE {
+            return Err(BeaconError::InvalidResponse("invalid metadata size"));
+        }
+        Ok(Self {
+            seq_number: u64::from_le_bytes(data[..8].try_into().unwrap()),
+            attnets: data[8..16].try_into().unwrap(),
+            syncnets: data[16],
+        })
+    }
+}
+
+/// Send our status and read the status of the peer
+pub async fn request_status<S>(stream: S, status: &Status) -> Result<Status, BeaconError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let response = request(stream, STATUS_PROTOCOL, &status.encode()).await?;
+    Status::decode(&response)
+}
+
+/// Read the metadata of the peer, the request has no payload
+pub async fn request_metadata<S>(stream: S) -> Result<MetaData, BeaconError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let response = request(stream, METADATA_PROTOCOL, &[]).await?;
+    MetaData::decode(&response)
+}
+
+/// Negotiate `protocol` on a new stream, send the request and read the single response chunk
+async fn request<S>(stream: S, protocol: &str, payload: &[u8]) -> Result<Vec<u8>, BeaconError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (_, mut stream) = dialer_select_proto(stream, [protocol], Version::V1)
+        .await
+        .map_err(Libp2pError::from)?;
+    trace!(protocol, "request negotiated, sending the request ...");
+
+    if !payload.is_empty() {
+        stream.write_all(&encode_payload(payload)?).await?;
+    }
+    stream.close().await?;
+
+    let mut response = Vec::new();
+    stream
+        .take(MAX_RESPONSE_SIZE)
+        .read_to_end(&mut response)
+        .await?;
+    let (&code, data) = response
+        .split_first()
+        .ok_or(BeaconError::InvalidResponse("empty response"))?;
+    let data = decode_payload(data)?;
+    match code {
+        0 => Ok(data),
+        code => Err(BeaconError::Refused {
+            code,
+            message: String::from_utf8_lossy(&data).into_owned(),
+        }),
+    }
+}
+
+/// Prefix the SSZ encoded message with its length and compress it with snappy frames
+pub fn encode_payload(ssz: &[u8]) -> Result<Vec<u8>, BeaconError> {
+    let mut buf = Vec::new();
+    write_varint(&mut buf, ssz.len() as u64);
+    let mut encoder = snap::write::FrameEncoder::new(&mut buf);
+    encoder.write_all(ssz)?;
+    encoder.flush()?;
+    drop(encoder);
+    Ok(buf)
+}
+
+/// Decompress a length prefixed snappy framed payload to the SSZ encoded message
+pub fn decode_payload(mut data: &[u8]) -> Result<Vec<u8>, BeaconError> {
+    let len = read_varint(&mut data)
+        .map_err(|_| BeaconError::InvalidResponse("invalid length prefix"))?;
+    if len > MAX_RESPONSE_SIZE {
+        return Err(BeaconError::InvalidResponse("payload too large"));
+    }
+    let mut ssz = vec![0; len as usize];
+    snap::read::FrameDecoder::new(data)
+        .read_exact(&mut ssz)
+        .map_err(|_| BeaconError::InvalidResponse("invalid snappy frames"))?;
+    Ok(ssz)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload_roundtrip() {
+        let status = Status {
+            fork_digest: [0x6a, 0x95, 0xa1, 0xa9],
+            finalized_root: [0x11; 32],
+            finalized_epoch: 310_000,
+            head_root: [0x22; 32],
+            head_slot: 9_920_123,
+        };
+        let payload = encode_payload(&status.encode()).unwrap();
+        assert_eq!(payload[0] as usize, Status::SIZE);
+        assert_eq!(
+            Status::decode(&decode_payload(&payload).unwrap()).unwrap(),
+            status
+        );
+
+        let metadata = MetaData {
+            seq_number: 42,
+            attnets: [0xff, 0, 0, 0, 0, 0, 0, 0x01],
+            syncnets: 0x03,
+        };
+        let ssz = decode_payload(&encode_payload(&metadata.encode()).unwrap()).unwrap();
+        assert_eq!(MetaData::decode(&ssz).unwrap(), metadata);
+
+        // Verify that a message of the wrong size is rejected
+        assert!(Status::decode(&metadata.encode()).is_err());
+        assert!(decode_payload(&[0x54, 0xff, 0x06]).is_err());
+    }
+}
@@ -1,39 +1,121 @@
 use measure_time::info_time;
 use std::{
-    net::{IpAddr, SocketAddr},
-    time::Duration,
+    path::PathBuf,
+    time::{Duration, Instant},
 };
-use tokio::net::TcpStream;
-use tracing::instrument;
+use tracing::{info, instrument};
 
-use self::stream::MessageStream;
-use crate::p2p::error::P2PError;
+use self::{chain::Profile, stream::MessageStream};
+use crate::{
+    p2p::{
+        connect::{Connector, Destination, Endpoints},
+        error::P2PError,
+        record::Recorder,
+    },
+    telemetry::HandshakeMetrics,
+};
 
+pub mod chain;
 pub mod codec;
+pub mod crawl;
+#[cfg(feature = "test-utils")]
+pub mod mock;
 pub mod stream;
+pub mod target;
+
+/// [`USER_AGENT`] is the user agent announced in the version message by default.
+pub const USER_AGENT: &str = "/Satoshi:25.0.0/";
 
 #[derive(Debug)]
 pub struct Config {
-    pub node_address: SocketAddr,
+    pub node_address: Destination,
     pub timeout: u64,
     pub user_agent: String,
+    /// Chain the node belongs to
+    pub profile: Profile,
+    pub connector: Connector,
+    /// Directory the session is recorded to
+    pub record: Option<PathBuf>,
+}
+
+/// A peer the handshake completed with, as described by its version message
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Peer {
+    pub endpoints: Endpoints,
+    pub version: u32,
+    /// The last network upgrade its version supports, for the chains tracking them
+    pub upgrade: Option<&'static str>,
+    pub user_agent: String,
+    pub start_height: i32,
+}
+
+impl Peer {
+    /// The version of the peer, e.g. `version: 170120 (NU6) user agent: /MagicBean:6.0.0/ height: 2726400`
+    pub fn info(&self) -> String {
+        format!(
+            "version: {}{} user agent: {} height: {}",
+            self.version,
+            self.upgrade
+                .map(|upgrade| format!(" ({})", upgrade))
+                .unwrap_or_default(),
+            self.user_agent,
+            self.start_height
+        )
+    }
 }
 
 /// Perform a P2P handshake with a peer
-#[instrument(level = "trace", skip_all, fields(peer=&*format!("{:?}", config.node_address)))]
-pub async fn handshake(config: Config) -> Result<IpAddr, P2PError> {
-    info_time!("[{:?}] Perform a P2P handshake", config.node_address);
+#[instrument(level = "trace", skip_all, fields(peer=&*format!("{}", config.node_address)))]
+pub async fn handshake(config: Config) -> Result<Peer, P2PError> {
+    info_time!("[{}] Perform a P2P handshake", config.node_address);
 
-    // Connect to the peer and perform the bitcoin network handshake
-    let transport = tokio::time::timeout(
+    let recorder = config.record.as_ref().map(|_| Recorder::default());
+    let res = exchange(&config, recorder.clone()).await;
+    if let (Some(dir), Some(recorder)) = (&config.record, recorder) {
+        let error = res.as_ref().err().map(P2PError::message);
+        recorder.save(
+            dir,
+            config.profile.name,
+            config.node_address.to_string(),
+            error,
+        );
+    }
+    res
+}
+
+/// Connect to the peer and perform the bitcoin network handshake
+async fn exchange(config: &Config, recorder: Option<Recorder>) -> Result<Peer, P2PError> {
+    let metrics = HandshakeMetrics::new(config.profile.name);
+
+    let started = Instant::now();
+    let connection = tokio::time::timeout(
         Duration::from_millis(config.timeout),
-        TcpStream::connect(config.node_address),
+        config.connector.connect(&config.node_address),
     )
     .await??;
+    metrics.phase("connect", started.elapsed());
 
-    MessageStream::new(config.node_address, config.user_agent)
-        .handshake(transport)
-        .await?;
+    let started = Instant::now();
+    let version = tokio::time::timeout(
+        Duration::from_millis(config.timeout),
+        MessageStream::new(
+            connection.endpoints.remote.announced_addr(),
+            config.user_agent.clone(),
+        )
+        .with_profile(config.profile)
+        .with_recorder(recorder)
+        .handshake(connection.stream),
+    )
+    .await??;
+    metrics.phase("version", started.elapsed());
 
-    Ok(config.node_address.ip())
+    let peer = Peer {
+        endpoints: connection.endpoints,
+        version: version.version,
+        upgrade: config.profile.upgrade(version.version),
+        user_agent: version.user_agent,
+        start_height: version.start_height,
+    };
+    info!("[{}] peer {}", config.node_address, peer.info());
+    Ok(peer)
 }
@@ -0,0 +1,178 @@
+use bitcoin::{
+    p2p::{Magic, PROTOCOL_VERSION},
+    Network,
+};
+use clap::ValueEnum;
+
+use crate::p2p::btc::USER_AGENT;
+
+/// A chain speaking the bitcoin p2p protocol
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Chain {
+    #[default]
+    Bitcoin,
+    Litecoin,
+    Dogecoin,
+    /// Handshaked by the `zec` subcommand
+    #[value(skip)]
+    Zcash,
+    /// Handshaked by the `bch` subcommand
+    #[value(skip)]
+    BitcoinCash,
+}
+
+/// [`ZCASH_UPGRADES`] are the protocol versions of the Zcash mainnet network upgrades.
+const ZCASH_UPGRADES: &[(u32, &str)] = &[
+    (170_005, "Overwinter"),
+    (170_007, "Sapling"),
+    (170_009, "Blossom"),
+    (170_011, "Heartwood"),
+    (170_013, "Canopy"),
+    (170_100, "NU5"),
+    (170_120, "NU6"),
+];
+
+/// The wire parameters a chain differs by
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Profile {
+    /// Short name the handshakes of the chain are reported and recorded under
+    pub name: &'static str,
+    /// Start bytes of every message
+    pub magic: Magic,
+    /// Protocol version announced in the version message
+    pub protocol_version: u32,
+    /// Port of the mainnet nodes
+    pub default_port: u16,
+    /// User agent of the reference client, announced by default
+    pub user_agent: &'static str,
+    /// Peers announcing an older protocol version are rejected
+    pub min_peer_version: Option<u32>,
+    /// Protocol versions of the network upgrades, oldest first
+    pub upgrades: &'static [(u32, &'static str)],
+    /// Feature messages sent to the peers before `verack`
+    pub features: Features,
+}
+
+/// The feature negotiation messages a chain supports, the others are not sent to its nodes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Features {
+    /// Addrv2 messages (BIP155)
+    pub sendaddrv2: bool,
+}
+
+impl Chain {
+    pub fn profile(self) -> Profile {
+        match self {
+            Chain::Bitcoin => Profile {
+                name: "btc",
+                magic: Network::Bitcoin.magic(),
+                protocol_version: PROTOCOL_VERSION,
+                default_port: 8333,
+                user_agent: USER_AGENT,
+                min_peer_version: None,
+                upgrades: &[],
+                features: Features { sendaddrv2: true },
+            },
+            Chain::Litecoin => Profile {
+                name: "ltc",
+                magic: Magic::from_bytes([0xfb, 0xc0, 0xb6, 0xdb]),
+                protocol_version: 70015,
+                default_port: 9333,
+                user_agent: "/LitecoinCore:0.21.2/",
+                min_peer_version: None,
+                upgrades: &[],
+                features: Features { sendaddrv2: true },
+            },
+            Chain::Dogecoin => Profile {
+                name: "doge",
+                magic: Magic::from_bytes([0xc0, 0xc0, 0xc0, 0xc0]),
+                protocol_version: 70015,
+                default_port: 22556,
+                user_agent: "/Shibetoshi:1.14.6/",
+                min_peer_version: None,
+                upgrades: &[],
+                features: Features::default(),
+            },
+            // Zcash keeps the bitcoin framing, its nodes drop the peers which do not support
+            // the last network upgrade once it activated
+            Chain::Zcash => Profile {
+                name: "zec",
+                magic: Magic::from_bytes([0x24, 0xe9, 0x27, 0x64]),
+                protocol_version: 170_120,
+                default_port: 8233,
+                user_agent: "/MagicBean:6.0.0/",
+                min_peer_version: Some(170_120),
+                upgrades: ZCASH_UPGRADES,
+                features: Features::default(),
+            },
+            // Bitcoin Cash never adopted segwit nor BIP155
+            Chain::BitcoinCash => Profile {
+                name: "bch",
+                magic: Magic::from_bytes([0xe3, 0xe1, 0xf3, 0xe8]),
+                protocol_version: 70016,
+                default_port: 8333,
+                user_agent: "/Bitcoin Cash Node:27.1.0(EB32.0)/",
+                min_peer_version: None,
+                upgrades: &[],
+                features: Features::default(),
+            },
+        }
+    }
+
+    /// The chain whose profile is named `name`
+    pub fn from_name(name: &str) -> Option<Self> {
+        Chain::value_variants()
+            .iter()
+            .copied()
+            .find(|chain| chain.profile().name == name)
+    }
+}
+
+impl Profile {
+    /// The last network upgrade supported by a protocol version
+    pub fn upgrade(&self, version: u32) -> Option<&'static str> {
+        self.upgrades
+            .iter()
+            .rev()
+            .find(|(activation, _)| version >= *activation)
+            .map(|(_, name)| *name)
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Chain::default().profile()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles() {
+        // Verify that every chain is found back from its name and has its own magic
+        for chain in Chain::value_variants() {
+            assert_eq!(Chain::from_name(chain.profile().name), Some(*chain));
+        }
+        assert_eq!(Chain::from_name("eth"), None);
+        assert_ne!(
+            Chain::Litecoin.profile().magic,
+            Chain::Bitcoin.profile().magic
+        );
+        assert_eq!(
+            Chain::Dogecoin.profile().magic.to_bytes(),
+            [0xc0, 0xc0, 0xc0, 0xc0]
+        );
+    }
+
+    #[test]
+    fn test_zcash_upgrades() {
+        let profile = Chain::Zcash.profile();
+        assert_eq!(profile.upgrade(170_002), None);
+        assert_eq!(profile.upgrade(170_013), Some("Canopy"));
+        assert_eq!(profile.upgrade(170_110), Some("NU5"));
+        assert_eq!(profile.upgrade(profile.protocol_version), Some("NU6"));
+        assert_eq!(Chain::Bitcoin.profile().upgrade(70016), None);
+    }
+}
@@ -1,11 +1,10 @@
 use bitcoin::{
-    consensus::{deserialize_partial, serialize},
+    consensus::{deserialize_partial, encode, serialize},
     p2p::{
         message::{NetworkMessage, RawNetworkMessage},
         message_network::VersionMessage,
         Address, ServiceFlags,
     },
-    Network,
 };
 use bytes::{Buf, BytesMut};
 use std::{
@@ -17,11 +16,24 @@ use std::{
 use tokio_util::codec::{Decoder, Encoder};
 use tracing::{instrument, trace};
 
+use crate::p2p::{
+    btc::chain::Profile,
+    record::{Direction, Recorder},
+};
+
+/// [`HEADER_SIZE`] is the size of the header of a message: magic, command, length and checksum.
+pub const HEADER_SIZE: usize = 24;
+
+/// [`MAX_MESSAGE_SIZE`] is the largest payload accepted, as in Bitcoin Core.
+pub const MAX_MESSAGE_SIZE: usize = 4_000_000;
+
 /// Tokio codec for RawNetworkMessage
 #[derive(Debug)]
-pub(crate) struct RawNetworkMessageCodec {
+pub struct RawNetworkMessageCodec {
     node_address: SocketAddr,
     user_agent: String,
+    profile: Profile,
+    recorder: Option<Recorder>,
 }
 
 /// Message types that can be sent over the stream
@@ -29,20 +41,33 @@ pub(crate) struct RawNetworkMessageCodec {
 pub enum NetworkMessageType {
     Version,
     Verack,
+    SendAddrV2,
+    GetAddr,
 }
 
 impl RawNetworkMessageCodec {
     /// Create a new client codec to encode/decode messages for initiating a connection
-    pub(crate) fn new_client(
-        node_address: SocketAddr,
-        user_agent: String,
-    ) -> Result<Self, io::Error> {
+    pub fn new_client(node_address: SocketAddr, user_agent: String) -> Result<Self, io::Error> {
         Ok(Self {
             node_address,
             user_agent,
+            profile: Profile::default(),
+            recorder: None,
         })
     }
 
+    /// Speak the protocol of another chain than bitcoin
+    pub fn with_profile(mut self, profile: Profile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Record the raw frames going through the codec
+    pub fn with_recorder(mut self, recorder: Option<Recorder>) -> Self {
+        self.recorder = recorder;
+        self
+    }
+
     fn version_message(&self) -> RawNetworkMessage {
         trace!("creating version message ...");
         let now = SystemTime::now()
@@ -52,7 +77,7 @@ impl RawNetworkMessageCodec {
 
         let sender = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0);
 
-        let btc_version = VersionMessage::new(
+        let mut btc_version = VersionMessage::new(
             ServiceFlags::NONE,
             now,
             Address::new(&self.node_address, ServiceFlags::NONE),
@@ -61,32 +86,94 @@ impl RawNetworkMessageCodec {
             self.user_agent.clone(),
             0,
         );
+        btc_version.version = self.profile.protocol_version;
 
-        RawNetworkMessage::new(
-            Network::Bitcoin.magic(),
-            NetworkMessage::Version(btc_version),
-        )
+        RawNetworkMessage::new(self.profile.magic, NetworkMessage::Version(btc_version))
     }
 
     pub fn verack_message(&self) -> RawNetworkMessage {
         trace!("creating verack message ...");
-        RawNetworkMessage::new(Network::Bitcoin.magic(), NetworkMessage::Verack)
+        RawNetworkMessage::new(self.profile.magic, NetworkMessage::Verack)
+    }
+
+    pub fn sendaddrv2_message(&self) -> RawNetworkMessage {
+        trace!("creating sendaddrv2 message ...");
+        RawNetworkMessage::new(self.profile.magic, NetworkMessage::SendAddrV2)
+    }
+
+    pub fn getaddr_message(&self) -> RawNetworkMessage {
+        trace!("creating getaddr message ...");
+        RawNetworkMessage::new(self.profile.magic, NetworkMessage::GetAddr)
+    }
+
+    /// Decode the next message of a stream of the expected network
+    fn decode_message(&self, buf: &mut BytesMut) -> Result<Option<RawNetworkMessage>, io::Error> {
+        match deserialize_partial::<RawNetworkMessage>(buf) {
+            Ok((message, count)) => {
+                trace!("decoding message ...");
+                if let Some(recorder) = &self.recorder {
+                    recorder.record(Direction::Received, &buf[..count]);
+                }
+
+                buf.advance(count);
+                Ok(Some(message))
+            }
+            // The message is not complete yet
+            Err(encode::Error::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(err) => Err(io::Error::new(io::ErrorKind::InvalidData, err)),
+        }
     }
 }
 
+/// The payload length announced by the header at the start of `buf`, once received
+fn payload_len(buf: &[u8]) -> Option<usize> {
+    let len = buf.get(16..20)?;
+    Some(u32::from_le_bytes(len.try_into().unwrap()) as usize)
+}
+
 impl Decoder for RawNetworkMessageCodec {
     type Item = RawNetworkMessage;
     type Error = io::Error;
 
     #[instrument(level = "trace", skip_all, fields(peer=&*format!("{:?}", self.node_address)))]
     fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        if let Ok((message, count)) = deserialize_partial::<RawNetworkMessage>(buf) {
-            trace!("decoding message ...");
+        // Reject the peers of another network, or not speaking the protocol at all, right away
+        let magic = self.profile.magic.to_bytes();
+        let res = if buf.len() >= magic.len() && buf[..magic.len()] != magic {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected network magic",
+            ))
+        } else if let Some(len) = payload_len(buf).filter(|len| *len > MAX_MESSAGE_SIZE) {
+            // Don't buffer a message which would be rejected once complete
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("message payload of {} bytes above the limit", len),
+            ))
+        } else {
+            self.decode_message(buf)
+        };
+        // Keep the rejected bytes too, they are what the peer sent
+        if let (Err(_), Some(recorder)) = (&res, &self.recorder) {
+            recorder.record(Direction::Received, buf);
+        }
+        res
+    }
 
-            buf.advance(count);
-            return Ok(Some(message));
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match self.decode(buf)? {
+            Some(message) => Ok(Some(message)),
+            None if buf.is_empty() => {
+                if let Some(recorder) = &self.recorder {
+                    recorder.close();
+                }
+                Ok(None)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed in the middle of a message",
+            )),
         }
-        Ok(None)
     }
 }
 
@@ -104,11 +191,44 @@ impl Encoder<NetworkMessageType> for RawNetworkMessageCodec {
                 trace!("encoding verack message ...");
                 self.verack_message()
             }
+            NetworkMessageType::SendAddrV2 => {
+                trace!("encoding sendaddrv2 message ...");
+                self.sendaddrv2_message()
+            }
+            NetworkMessageType::GetAddr => {
+                trace!("encoding getaddr message ...");
+                self.getaddr_message()
+            }
         };
 
         // Serialize the message and write it to the buffer
         let data = serialize(&msg);
+        if let Some(recorder) = &self.recorder {
+            recorder.record(Direction::Sent, &data);
+        }
         buf.extend_from_slice(&data);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_oversized_message() {
+        let mut codec =
+            RawNetworkMessageCodec::new_client(([127, 0, 0, 1], 8333).into(), String::new())
+                .unwrap();
+        let mut buf = BytesMut::from(&serialize(&codec.verack_message())[..]);
+        buf[16..20].copy_from_slice(&(MAX_MESSAGE_SIZE as u32 + 1).to_le_bytes());
+
+        // Verify that an oversized message is rejected from its header, not waited for
+        let err = codec.decode(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // Verify that a message of the maximum size is waited for
+        buf[16..20].copy_from_slice(&(MAX_MESSAGE_SIZE as u32).to_le_bytes());
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+    }
+}
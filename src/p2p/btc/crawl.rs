@@ -0,0 +1,138 @@
+use bitcoin::p2p::{message_network::VersionMessage, ServiceFlags};
+use futures::{stream, StreamExt};
+use std::{collections::HashSet, net::SocketAddr, time::Duration};
+use tracing::{debug, instrument};
+
+use crate::p2p::{
+    btc::{chain::Profile, stream::MessageStream},
+    connect::{Connector, Destination},
+    error::P2PError,
+};
+
+#[derive(Debug)]
+pub struct CrawlConfig {
+    pub seeds: Vec<Destination>,
+    pub timeout: u64,
+    pub addr_timeout: u64,
+    pub user_agent: String,
+    pub profile: Profile,
+    pub max_depth: usize,
+    pub max_nodes: usize,
+    pub concurrency: usize,
+    pub connector: Connector,
+}
+
+/// A node which completed the handshake during the crawl
+#[derive(Clone, Debug)]
+pub struct CrawledNode {
+    pub address: Destination,
+    /// The local address the node was reached from
+    pub local: SocketAddr,
+    pub depth: usize,
+    pub version: u32,
+    pub services: ServiceFlags,
+    pub user_agent: String,
+    pub start_height: i32,
+}
+
+/// Crawl the network starting from the seed addresses.
+///
+/// Every reachable node is asked for the addresses it knows about, and those are handshaked in
+/// turn until `max_nodes` probed addresses is reached. The nodes up to `max_depth` hops from the
+/// seeds are handshaked, the farthest ones without being asked for their addresses.
+#[instrument(level = "trace", skip_all)]
+pub async fn crawl(config: CrawlConfig) -> Vec<CrawledNode> {
+    let config = &config;
+    let mut seen: HashSet<Destination> = HashSet::new();
+    let mut frontier: Vec<Destination> = config
+        .seeds
+        .iter()
+        .filter(|addr| seen.insert((*addr).clone()))
+        .cloned()
+        .take(config.max_nodes)
+        .collect();
+    let mut reachable = Vec::new();
+
+    for depth in 0..=config.max_depth {
+        if frontier.is_empty() {
+            break;
+        }
+        debug!("crawling {} addresses at depth {}", frontier.len(), depth);
+
+        let results: Vec<_> = stream::iter(std::mem::take(&mut frontier))
+            .map(|address| async move {
+                let res = probe(&address, config).await;
+                (address, res)
+            })
+            .buffer_unordered(config.concurrency.max(1))
+            .collect()
+            .await;
+
+        for (address, result) in results {
+            let (local, version, addrs) = match result {
+                Ok(res) => res,
+                Err(err) => {
+                    debug!("[unreachable] [{}] error: {}", address, err);
+                    continue;
+                }
+            };
+
+            reachable.push(CrawledNode {
+                address,
+                local,
+                depth,
+                version: version.version,
+                services: version.services,
+                user_agent: version.user_agent,
+                start_height: version.start_height,
+            });
+
+            if depth < config.max_depth {
+                for addr in addrs {
+                    if seen.len() >= config.max_nodes {
+                        break;
+                    }
+                    // Onion services are only reachable through a proxy
+                    if config.connector.proxy.is_none() && addr.socket_addr().is_none() {
+                        continue;
+                    }
+                    if seen.insert(addr.clone()) {
+                        frontier.push(addr);
+                    }
+                }
+            }
+        }
+    }
+
+    reachable
+}
+
+/// Handshake a single address and collect the addresses it knows about
+async fn probe(
+    address: &Destination,
+    config: &CrawlConfig,
+) -> Result<(SocketAddr, VersionMessage, Vec<Destination>), P2PError> {
+    let connection = tokio::time::timeout(
+        Duration::from_millis(config.timeout),
+        config.connector.connect(address),
+    )
+    .await??;
+
+    // Bound the whole exchange so a stalled peer does not hold up the crawl
+    let local = connection.endpoints.local;
+    let (version, addrs) = tokio::time::timeout(
+        Duration::from_millis(config.timeout + config.addr_timeout),
+        MessageStream::new(
+            connection.endpoints.remote.announced_addr(),
+            config.user_agent.clone(),
+        )
+        .with_profile(config.profile)
+        .get_addr(
+            connection.stream,
+            Duration::from_millis(config.addr_timeout),
+        ),
+    )
+    .await??;
+
+    Ok((local, version, addrs))
+}
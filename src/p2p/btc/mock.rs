@@ -0,0 +1,184 @@
+use bitcoin::{
+    consensus::serialize,
+    p2p::{
+        message::{NetworkMessage, RawNetworkMessage},
+        message_network::VersionMessage,
+        Address, ServiceFlags,
+    },
+    Network,
+};
+use std::{
+    io,
+    net::SocketAddr,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{io::AsyncWriteExt, net::TcpStream};
+use tokio_stream::StreamExt;
+use tokio_util::codec::Decoder;
+
+use crate::p2p::{
+    btc::{chain::Profile, codec::RawNetworkMessageCodec},
+    mock::MockServer,
+};
+
+/// How the mock peer answers the connections
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Behavior {
+    /// Complete the handshake and answer `getaddr` like a regular node
+    #[default]
+    Honest,
+    /// Send `verack` before its own `version`
+    VerackFirst,
+    /// Accept the connection and never answer
+    Stall,
+    /// Answer with bytes which are not bitcoin messages
+    Garbage,
+    /// Answer with the messages of another network (testnet)
+    WrongMagic,
+    /// Close the connection once the `version` of the client is received
+    Disconnect,
+}
+
+#[derive(Clone, Debug)]
+pub struct MockConfig {
+    pub behavior: Behavior,
+    /// Chain the peer belongs to
+    pub profile: Profile,
+    pub user_agent: String,
+    pub start_height: i32,
+    /// Addresses sent in reply to `getaddr`
+    pub addrs: Vec<SocketAddr>,
+}
+
+impl From<Behavior> for MockConfig {
+    fn from(behavior: Behavior) -> Self {
+        Self {
+            behavior,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        Self {
+            behavior: Behavior::default(),
+            profile: Profile::default(),
+            user_agent: "/mock:0.1.0/".to_string(),
+            start_height: 0,
+            addrs: Vec::new(),
+        }
+    }
+}
+
+/// A scripted bitcoin peer listening on a local port, stopped when dropped
+#[derive(Debug)]
+pub struct MockPeer {
+    server: MockServer<&'static str>,
+}
+
+impl MockPeer {
+    /// Listen on a random local port and answer every connection as configured
+    pub async fn spawn(config: MockConfig) -> io::Result<Self> {
+        let server = MockServer::spawn(move |stream, peer, received| {
+            let config = config.clone();
+            async move { serve(stream, peer, &config, &received).await }
+        })
+        .await?;
+        Ok(Self { server })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.server.addr()
+    }
+
+    /// Commands of the messages received so far, over all the connections
+    pub fn received(&self) -> Vec<&'static str> {
+        self.server.received()
+    }
+}
+
+/// Answer a single connection
+async fn serve(
+    stream: TcpStream,
+    peer: SocketAddr,
+    config: &MockConfig,
+    received: &Mutex<Vec<&'static str>>,
+) -> io::Result<()> {
+    let local = stream.local_addr()?;
+    let mut transport = RawNetworkMessageCodec::new_client(peer, config.user_agent.clone())?
+        .with_profile(config.profile)
+        .framed(stream);
+
+    while let Some(msg) = transport.try_next().await? {
+        received.lock().unwrap().push(msg.payload().cmd());
+
+        let features = config.profile.features;
+        let replies = match (config.behavior, msg.payload()) {
+            (Behavior::Stall, _) => continue,
+            // Drop the peers sending feature messages the chain does not support
+            (_, NetworkMessage::SendAddrV2) if !features.sendaddrv2 => return Ok(()),
+            (Behavior::Disconnect, NetworkMessage::Version(_)) => return Ok(()),
+            (Behavior::Garbage, NetworkMessage::Version(_)) => {
+                transport
+                    .get_mut()
+                    .write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n")
+                    .await?;
+                continue;
+            }
+            (behavior, NetworkMessage::Version(_)) => {
+                let version = NetworkMessage::Version(version_message(config, local, peer));
+                let magic = config.profile.magic;
+                let (magic, replies) = match behavior {
+                    Behavior::VerackFirst => (magic, [NetworkMessage::Verack, version]),
+                    Behavior::WrongMagic => {
+                        (Network::Testnet.magic(), [version, NetworkMessage::Verack])
+                    }
+                    _ => (magic, [version, NetworkMessage::Verack]),
+                };
+                replies
+                    .map(|reply| RawNetworkMessage::new(magic, reply))
+                    .to_vec()
+            }
+            (_, NetworkMessage::GetAddr) => {
+                let now = unix_time() as u32;
+                let entries = config
+                    .addrs
+                    .iter()
+                    .map(|addr| (now, Address::new(addr, ServiceFlags::NETWORK)))
+                    .collect();
+                vec![RawNetworkMessage::new(
+                    config.profile.magic,
+                    NetworkMessage::Addr(entries),
+                )]
+            }
+            _ => continue,
+        };
+        for reply in replies {
+            transport.get_mut().write_all(&serialize(&reply)).await?;
+        }
+    }
+    Ok(())
+}
+
+fn version_message(config: &MockConfig, local: SocketAddr, peer: SocketAddr) -> VersionMessage {
+    let now = unix_time();
+    let mut version = VersionMessage::new(
+        ServiceFlags::NETWORK,
+        now as i64,
+        Address::new(&peer, ServiceFlags::NONE),
+        Address::new(&local, ServiceFlags::NETWORK),
+        now,
+        config.user_agent.clone(),
+        config.start_height,
+    );
+    version.version = config.profile.protocol_version;
+    version
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
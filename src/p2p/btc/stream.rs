@@ -1,18 +1,27 @@
-use bitcoin::p2p::message::NetworkMessage;
+use bitcoin::p2p::{address::AddrV2, message::NetworkMessage, message_network::VersionMessage};
 use futures::SinkExt;
-use std::{fmt::Debug, io, net::SocketAddr};
-use tokio::net::TcpStream;
+use std::{collections::HashSet, fmt::Debug, io, net::SocketAddr, time::Duration};
+use tokio::{net::TcpStream, time::Instant};
 use tokio_stream::StreamExt;
-use tokio_util::codec::Decoder;
+use tokio_util::codec::{Decoder, Framed};
 use tracing::{instrument, trace};
 
-use crate::p2p::btc::codec::{NetworkMessageType, RawNetworkMessageCodec};
+use crate::p2p::{
+    btc::{
+        chain::Profile,
+        codec::{NetworkMessageType, RawNetworkMessageCodec},
+    },
+    connect::{onion_host, Destination},
+    record::Recorder,
+};
 
 /// Bitcoin Message handshake over TCP exchanging raw bytes
 #[derive(Debug)]
 pub struct MessageStream {
     node_address: SocketAddr,
     user_agent: String,
+    profile: Profile,
+    recorder: Option<Recorder>,
 }
 
 impl MessageStream {
@@ -20,30 +29,146 @@ impl MessageStream {
         Self {
             node_address,
             user_agent,
+            profile: Profile::default(),
+            recorder: None,
         }
     }
 
-    /// Perform an initial handshake with a peer
+    /// Speak the protocol of another chain than bitcoin
+    pub fn with_profile(mut self, profile: Profile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Record the raw frames exchanged with the peer
+    pub fn with_recorder(mut self, recorder: Option<Recorder>) -> Self {
+        self.recorder = recorder;
+        self
+    }
+
+    /// Perform an initial handshake with a peer and return its version message
     #[instrument(skip_all, fields(peer=&*format!("{:?}", self.node_address)))]
-    pub async fn handshake(&self, stream: TcpStream) -> Result<(), io::Error> {
+    pub async fn handshake(&self, stream: TcpStream) -> Result<VersionMessage, io::Error> {
+        let mut transport = self.framed(stream)?;
+        self.exchange_versions(&mut transport).await
+    }
+
+    /// Perform an initial handshake with a peer, then ask it for the addresses it knows about.
+    ///
+    /// Addresses are collected from `addr` and `addrv2` replies until the peer answers with more
+    /// than a single (self-announcement) entry or `timeout` elapses.
+    #[instrument(skip_all, fields(peer=&*format!("{:?}", self.node_address)))]
+    pub async fn get_addr(
+        &self,
+        stream: TcpStream,
+        timeout: Duration,
+    ) -> Result<(VersionMessage, Vec<Destination>), io::Error> {
+        let mut transport = self.framed(stream)?;
+        let version = self.exchange_versions(&mut transport).await?;
+
+        trace!("sending getaddr message ...");
+        transport.send(NetworkMessageType::GetAddr).await?;
+
+        let deadline = Instant::now() + timeout;
+        let mut addrs = HashSet::new();
+        loop {
+            let msg = match tokio::time::timeout_at(deadline, transport.try_next()).await {
+                Ok(Ok(Some(msg))) => msg,
+                Ok(Ok(None)) => break,
+                Ok(Err(err)) => {
+                    trace!(?err, "stream error while waiting for addresses");
+                    break;
+                }
+                Err(_) => {
+                    trace!("timed out waiting for addresses");
+                    break;
+                }
+            };
+            let received = match msg.payload() {
+                NetworkMessage::Addr(entries) => {
+                    trace!("received addr message with {} entries", entries.len());
+                    addrs.extend(
+                        entries
+                            .iter()
+                            .filter_map(|(_, a)| a.socket_addr().ok().map(Destination::Addr)),
+                    );
+                    entries.len()
+                }
+                NetworkMessage::AddrV2(entries) => {
+                    trace!("received addrv2 message with {} entries", entries.len());
+                    addrs.extend(entries.iter().filter_map(|a| match a.addr {
+                        // Onion services are kept as hostnames, to be reached through a proxy
+                        AddrV2::TorV3(pubkey) => Some(Destination::Host {
+                            host: onion_host(&pubkey),
+                            port: a.port,
+                        }),
+                        _ => a.socket_addr().ok().map(Destination::Addr),
+                    }));
+                    entries.len()
+                }
+                _ => {
+                    trace!("received other message while waiting for addresses");
+                    0
+                }
+            };
+            if received > 1 {
+                break;
+            }
+        }
+
+        Ok((version, addrs.into_iter().collect()))
+    }
+
+    fn framed(
+        &self,
+        stream: TcpStream,
+    ) -> Result<Framed<TcpStream, RawNetworkMessageCodec>, io::Error> {
         let codec_client =
             RawNetworkMessageCodec::new_client(self.node_address, self.user_agent.clone())
-                .map_err(|_| io::Error::new(io::ErrorKind::Other, "invalid handshake"))?;
+                .map_err(|_| io::Error::new(io::ErrorKind::Other, "invalid handshake"))?
+                .with_profile(self.profile)
+                .with_recorder(self.recorder.clone());
 
-        let mut transport = codec_client.framed(stream);
+        Ok(codec_client.framed(stream))
+    }
 
+    /// Exchange `version`/`verack` messages until both sides have acknowledged each other
+    async fn exchange_versions(
+        &self,
+        transport: &mut Framed<TcpStream, RawNetworkMessageCodec>,
+    ) -> Result<VersionMessage, io::Error> {
         trace!("sending version message ...");
         transport.send(NetworkMessageType::Version).await?;
 
+        let mut version = None;
+        let mut verack = false;
         while let Some(msg) = transport.try_next().await? {
             match msg.payload() {
                 NetworkMessage::Verack => {
                     trace!("received verack message ...");
-                    // Verack received, handshake is complete
-                    break;
+                    verack = true;
                 }
-                NetworkMessage::Version(_) => {
+                NetworkMessage::Version(peer_version) => {
                     trace!("received version message");
+                    if let Some(min) = self.profile.min_peer_version {
+                        if peer_version.version < min {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!(
+                                    "obsolete peer protocol version {}, {} is required",
+                                    peer_version.version, min
+                                ),
+                            ));
+                        }
+                    }
+                    version = Some(peer_version.clone());
+                    // Negotiate the features supported by the chain, this must happen
+                    // before our verack
+                    let features = self.profile.features;
+                    if features.sendaddrv2 {
+                        trace!("sending sendaddrv2 ...");
+                        transport.send(NetworkMessageType::SendAddrV2).await?;
+                    }
                     trace!("sending verack ...");
                     // Received another Version message, send a Verack in response
                     transport.send(NetworkMessageType::Verack).await?;
@@ -52,9 +177,19 @@ impl MessageStream {
                     trace!("received unexpected for handshake other message");
                 }
             }
+
+            if verack {
+                if let Some(version) = version.take() {
+                    // Version and Verack received, handshake is complete
+                    return Ok(version);
+                }
+            }
         }
 
-        Ok(())
+        Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "connection closed before handshake completed",
+        ))
     }
 }
 
@@ -63,7 +198,15 @@ mod tests {
     use std::net::{IpAddr, Ipv4Addr};
 
     use super::*;
-    use tokio::net::{TcpListener, TcpStream};
+    use bitcoin::{
+        consensus::serialize,
+        p2p::{message::RawNetworkMessage, Address, ServiceFlags},
+        Network,
+    };
+    use tokio::{
+        io::AsyncWriteExt,
+        net::{TcpListener, TcpStream},
+    };
 
     #[tokio::test]
     async fn test_handshake_passthrough() {
@@ -92,4 +235,64 @@ mod tests {
         // make sure the server receives the message and asserts before ending the test
         handle.await.unwrap();
     }
+
+    #[tokio::test]
+    async fn test_get_addr() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let known: Vec<SocketAddr> = vec![
+            "10.0.0.1:8333".parse().unwrap(),
+            "[2001:db8::1]:8333".parse().unwrap(),
+        ];
+
+        let announced = known.clone();
+        let handle = tokio::spawn(async move {
+            let (incoming, _) = listener.accept().await.unwrap();
+            let mut transport = RawNetworkMessageCodec::new_client(addr, "/test/".to_string())
+                .unwrap()
+                .framed(incoming);
+            transport.send(NetworkMessageType::Version).await.unwrap();
+
+            // Answer the handshake, then reply to getaddr with the known addresses
+            while let Some(msg) = transport.try_next().await.unwrap() {
+                match msg.payload() {
+                    NetworkMessage::Version(_) => {
+                        transport.send(NetworkMessageType::Verack).await.unwrap()
+                    }
+                    NetworkMessage::GetAddr => {
+                        let entries = announced
+                            .iter()
+                            .map(|a| (0, Address::new(a, ServiceFlags::NETWORK)))
+                            .collect();
+                        let reply = RawNetworkMessage::new(
+                            Network::Bitcoin.magic(),
+                            NetworkMessage::Addr(entries),
+                        );
+                        transport
+                            .get_mut()
+                            .write_all(&serialize(&reply))
+                            .await
+                            .unwrap();
+                        break;
+                    }
+                    _ => (),
+                }
+            }
+        });
+
+        let outgoing = TcpStream::connect(addr).await.unwrap();
+        let (version, mut addrs) = MessageStream::new(addr, "/Satoshi:25.0.0/".to_string())
+            .get_addr(outgoing, Duration::from_secs(5))
+            .await
+            .unwrap();
+
+        // Verify that the peer version was captured and the addresses were collected
+        assert_eq!(version.user_agent, "/test/");
+        addrs.sort();
+        let mut expected: Vec<Destination> = known.into_iter().map(Destination::from).collect();
+        expected.sort();
+        assert_eq!(addrs, expected);
+
+        handle.await.unwrap();
+    }
 }
@@ -0,0 +1,205 @@
+use std::{fmt, net::SocketAddr, str::FromStr};
+use tracing::{debug, error};
+
+use crate::p2p::{connect::Destination, resolver::Resolver};
+
+/// A handshake target given on the command line
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// A single node: `<ip_address>:<port>` or `<hostname>:<port>`
+    Node(Destination),
+    /// Every node returned by a DNS seed: `dnsseed:<name>[:<port>]`, on the default port of the
+    /// chain when omitted
+    DnsSeed { name: String, port: Option<u16> },
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(seed) = s.strip_prefix("dnsseed:") else {
+            return s.parse().map(Target::Node);
+        };
+
+        let (name, port) = match seed.rsplit_once(':') {
+            Some((name, port)) => (name, Some(port.parse().map_err(|_| "invalid port")?)),
+            None => (seed, None),
+        };
+        if name.is_empty() {
+            return Err("empty DNS seed name".to_string());
+        }
+        Ok(Target::DnsSeed {
+            name: name.to_string(),
+            port,
+        })
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Node(destination) => write!(f, "{}", destination),
+            Target::DnsSeed {
+                name,
+                port: Some(port),
+            } => write!(f, "dnsseed:{}:{}", name, port),
+            Target::DnsSeed { name, port: None } => write!(f, "dnsseed:{}", name),
+        }
+    }
+}
+
+/// Expand the targets into the destinations to handshake.
+///
+/// Hostnames are kept as they are and resolved when connecting, while DNS seeds are expanded
+/// into every address they return. Seeds are queried for the nodes advertising `services` when
+/// given, through the `x<hex services>.` subdomain filter supported by the seeders, and
+/// handshaked on `default_port` unless the seed has its own.
+pub async fn resolve_targets<R: Resolver + ?Sized>(
+    targets: Vec<Target>,
+    services: Option<u64>,
+    default_port: u16,
+    resolver: &R,
+) -> Vec<Destination> {
+    let mut destinations = Vec::new();
+    for target in targets {
+        let (name, port) = match &target {
+            Target::Node(destination) => {
+                destinations.push(destination.clone());
+                continue;
+            }
+            Target::DnsSeed { name, port } => match services {
+                Some(services) => (
+                    format!("x{:x}.{}", services, name),
+                    port.unwrap_or(default_port),
+                ),
+                None => (name.clone(), port.unwrap_or(default_port)),
+            },
+        };
+
+        destinations.extend(
+            lookup(resolver, &target, &name, port)
+                .await
+                .into_iter()
+                .map(Destination::Addr),
+        );
+    }
+    destinations
+}
+
+/// Expand the targets into socket addresses, resolving the hostnames upfront
+pub async fn resolve_addrs<R: Resolver + ?Sized>(
+    targets: Vec<Target>,
+    services: Option<u64>,
+    default_port: u16,
+    resolver: &R,
+) -> Vec<SocketAddr> {
+    let mut addrs = Vec::new();
+    for destination in resolve_targets(targets, services, default_port, resolver).await {
+        match &destination {
+            Destination::Addr(addr) => addrs.push(*addr),
+            Destination::Host { host, port } => {
+                let target = Target::Node(destination.clone());
+                addrs.extend(lookup(resolver, &target, host, *port).await)
+            }
+        }
+    }
+    addrs
+}
+
+async fn lookup<R: Resolver + ?Sized>(
+    resolver: &R,
+    target: &Target,
+    host: &str,
+    port: u16,
+) -> Vec<SocketAddr> {
+    match resolver.lookup_ip(host).await {
+        Ok(ips) if ips.is_empty() => {
+            error!("[failed] [{}] error: no address found", target);
+            Vec::new()
+        }
+        Ok(ips) => {
+            debug!("[{}] resolved {} addresses", target, ips.len());
+            ips.into_iter()
+                .map(|ip| SocketAddr::new(ip, port))
+                .collect()
+        }
+        Err(err) => {
+            error!("[failed] [{}] error: {}", target, err);
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::p2p::resolver::StaticResolver;
+
+    #[test]
+    fn test_parse_targets() {
+        assert_eq!(
+            "127.0.0.1:8333".parse::<Target>().unwrap(),
+            Target::Node(Destination::Addr("127.0.0.1:8333".parse().unwrap()))
+        );
+        assert_eq!(
+            "node.example.org:18333".parse::<Target>().unwrap(),
+            Target::Node(Destination::Host {
+                host: "node.example.org".to_string(),
+                port: 18333
+            })
+        );
+        assert_eq!(
+            "dnsseed:seed.bitcoin.sipa.be".parse::<Target>().unwrap(),
+            Target::DnsSeed {
+                name: "seed.bitcoin.sipa.be".to_string(),
+                port: None
+            }
+        );
+        assert_eq!(
+            "dnsseed:seed.litecoin.net:19335".parse::<Target>().unwrap(),
+            Target::DnsSeed {
+                name: "seed.litecoin.net".to_string(),
+                port: Some(19335)
+            }
+        );
+        assert!("node.example.org".parse::<Target>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_targets() {
+        let mut resolver = StaticResolver::default();
+        resolver.insert_ip(
+            "x9.seed.example.org",
+            vec!["10.0.0.1".parse().unwrap(), "2001:db8::1".parse().unwrap()],
+        );
+        resolver.insert_ip("node.example.org", vec!["10.0.0.2".parse().unwrap()]);
+
+        let targets: Vec<Target> = vec![
+            "dnsseed:seed.example.org".parse().unwrap(),
+            "node.example.org:18333".parse().unwrap(),
+            "10.0.0.3:8333".parse().unwrap(),
+            "dnsseed:missing.example.org".parse().unwrap(),
+        ];
+
+        // Verify that every record of the filtered seed subdomain is handshaked, and that
+        // hostnames are left to be resolved when connecting
+        let destinations = resolve_targets(targets.clone(), Some(9), 8333, &resolver).await;
+        let expected: Vec<Destination> = vec![
+            Destination::Addr("10.0.0.1:8333".parse().unwrap()),
+            Destination::Addr("[2001:db8::1]:8333".parse().unwrap()),
+            "node.example.org:18333".parse().unwrap(),
+            Destination::Addr("10.0.0.3:8333".parse().unwrap()),
+        ];
+        assert_eq!(destinations, expected);
+
+        // Verify that hostnames are resolved when socket addresses are required
+        let addrs = resolve_addrs(targets, Some(9), 8333, &resolver).await;
+        let expected: Vec<SocketAddr> = vec![
+            "10.0.0.1:8333".parse().unwrap(),
+            "[2001:db8::1]:8333".parse().unwrap(),
+            "10.0.0.2:18333".parse().unwrap(),
+            "10.0.0.3:8333".parse().unwrap(),
+        ];
+        assert_eq!(addrs, expected);
+    }
+}
@@ -1,21 +1,291 @@
-use std::net::SocketAddr;
-
-use clap::Subcommand;
+use clap::{Args, Subcommand, ValueEnum};
+use discv5::Enr;
+use libp2p::Multiaddr;
 use reth_primitives::NodeRecord;
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+
+use crate::p2p::{
+    beacon::network::{ForkDigest, Network},
+    btc::{self, chain::Chain},
+    eth::target::Target,
+    ln,
+};
 
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     /// Perform a P2P handshake with the ethereum network nodes
-    Eth { nodes_addrs: Vec<NodeRecord> },
-    /// Perform a P2P handshake with the bitcoin network nodes
+    Eth {
+        #[arg(help = "enode URLs or enrtree:// node lists to perform the handshake with")]
+        nodes_addrs: Vec<Target>,
+        #[command(subcommand)]
+        command: Option<EthCommands>,
+    },
+    /// Perform a P2P handshake with the bitcoin network nodes, or the ones of its forks
     Btc {
-        nodes_addrs: Vec<SocketAddr>,
+        #[arg(
+            help = "ip_address:port, hostname:port, <onion>.onion:port or dnsseed:<name>[:port] targets"
+        )]
+        nodes_addrs: Vec<btc::target::Target>,
+        #[arg(
+            long,
+            global = true,
+            value_enum,
+            default_value_t = Chain::Bitcoin,
+            help = "chain speaking the bitcoin protocol the nodes belong to"
+        )]
+        chain: Chain,
+        #[arg(
+            long,
+            short,
+            global = true,
+            help = "the user agent to be used during handshake operation [default: the one of the chain reference client]"
+        )]
+        user_agent: Option<String>,
+        #[arg(
+            long,
+            global = true,
+            value_parser = parse_services,
+            help = "only query DNS seeds for nodes with these service bits (hex, e.g. 9)"
+        )]
+        seed_services: Option<u64>,
+        #[command(subcommand)]
+        command: Option<BtcCommands>,
+    },
+    /// Perform a P2P handshake with the zcash network nodes
+    Zec {
+        #[arg(help = "ip_address:port, hostname:port or dnsseed:<name>[:port] targets")]
+        nodes_addrs: Vec<btc::target::Target>,
         #[arg(
             long,
             short,
-            help = "the user agent to be used during handshake operation",
-            default_value = "/Satoshi:25.0.0/"
+            help = "the user agent to be used during handshake operation [default: the one of zcashd]"
+        )]
+        user_agent: Option<String>,
+    },
+    /// Perform a P2P handshake with the bitcoin cash network nodes
+    Bch {
+        #[arg(help = "ip_address:port, hostname:port or dnsseed:<name>[:port] targets")]
+        nodes_addrs: Vec<btc::target::Target>,
+        #[arg(
+            long,
+            short,
+            help = "the user agent to be used during handshake operation [default: the one of Bitcoin Cash Node]"
+        )]
+        user_agent: Option<String>,
+        #[arg(
+            long,
+            value_parser = parse_services,
+            help = "only query DNS seeds for nodes with these service bits (hex, e.g. 1)"
+        )]
+        seed_services: Option<u64>,
+    },
+    /// Perform a libp2p handshake (noise, yamux and identify) with the nodes
+    Libp2p {
+        #[arg(
+            help = "multiaddrs to perform the handshake with, e.g. /ip4/<ip_address>/tcp/<port>/p2p/<peer id>"
+        )]
+        nodes_addrs: Vec<Multiaddr>,
+    },
+    /// Exchange the eth2 status and metadata with the beacon nodes over libp2p
+    Beacon {
+        #[arg(
+            help = "multiaddrs of the beacon nodes, e.g. /ip4/<ip_address>/tcp/9000/p2p/<peer id>"
+        )]
+        nodes_addrs: Vec<Multiaddr>,
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = Network::Mainnet,
+            help = "network the nodes are expected to follow"
+        )]
+        network: Network,
+        #[arg(
+            long,
+            value_parser = parse_fork_digest,
+            help = "expected fork digest (in hex) of the nodes, instead of the forks of --network"
+        )]
+        fork_digest: Option<ForkDigest>,
+    },
+    /// Perform the BOLT-8 handshake and exchange init messages with the lightning nodes
+    Ln {
+        #[arg(help = "nodes to perform the handshake with, e.g. <node_id>@<ip_address>:9735")]
+        nodes_addrs: Vec<ln::target::Target>,
+        #[arg(
+            long,
+            default_value_t = bitcoin::Network::Bitcoin,
+            help = "chain announced to the nodes: bitcoin, testnet, signet or regtest"
         )]
-        user_agent: String,
+        network: bitcoin::Network,
     },
+    /// Show the uptime and the latency of the peers recorded with --db
+    History(HistoryArgs),
+    /// Serve an HTTP JSON API performing the handshakes on demand
+    Serve(ServeArgs),
+    /// Play the peer side of sessions recorded with --record back against the client
+    Replay(ReplayArgs),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum EthCommands {
+    /// Discover ethereum nodes over discv4 (or discv5 with --discovery v5) and perform a P2P
+    /// handshake with them
+    Crawl(EthCrawlArgs),
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Discovery {
+    #[default]
+    V4,
+    V5,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct EthCrawlArgs {
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Discovery::V4,
+        help = "discovery protocol used to find the nodes"
+    )]
+    pub discovery: Discovery,
+    #[arg(help = "discv4 bootnodes to start crawling from [default: holesky bootnodes]")]
+    pub bootnodes: Vec<NodeRecord>,
+    #[arg(
+        long = "enr",
+        required_if_eq("discovery", "v5"),
+        help = "discv5 bootnode records to start crawling from"
+    )]
+    pub bootnode_enrs: Vec<Enr>,
+    #[arg(
+        long,
+        default_value_t = 16,
+        help = "number of random lookups performed over discv5"
+    )]
+    pub lookups: usize,
+    #[arg(
+        long,
+        default_value_t = 3,
+        help = "maximum number of hops away from the bootnodes, the farthest nodes are not queried"
+    )]
+    pub max_depth: usize,
+    #[arg(
+        long,
+        default_value_t = 200,
+        help = "maximum number of nodes to discover"
+    )]
+    pub max_nodes: usize,
+    #[arg(
+        long,
+        default_value_t = 64,
+        help = "maximum number of concurrent discovery lookups"
+    )]
+    pub concurrency: usize,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum BtcCommands {
+    /// Discover reachable bitcoin nodes by recursively asking peers for their addresses
+    Crawl(BtcCrawlArgs),
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct BtcCrawlArgs {
+    #[arg(required = true, help = "seed targets to start crawling from")]
+    pub seeds: Vec<btc::target::Target>,
+    #[arg(
+        long,
+        default_value_t = 2,
+        help = "maximum number of hops away from the seed addresses, the farthest nodes are not queried"
+    )]
+    pub max_depth: usize,
+    #[arg(
+        long,
+        default_value_t = 1000,
+        help = "maximum number of addresses to handshake"
+    )]
+    pub max_nodes: usize,
+    #[arg(
+        long,
+        default_value_t = 64,
+        help = "maximum number of concurrent handshakes"
+    )]
+    pub concurrency: usize,
+    #[arg(
+        long,
+        default_value_t = 30000,
+        help = "time to wait for the addr reply after getaddr (in ms)"
+    )]
+    pub addr_timeout: u64,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct HistoryArgs {
+    #[arg(long, help = "only show this target")]
+    pub target: Option<String>,
+    #[arg(
+        long,
+        value_parser = humantime::parse_duration,
+        help = "only show the attempts made in this last period, e.g. 24h"
+    )]
+    pub since: Option<Duration>,
+    #[arg(
+        long,
+        value_parser = humantime::parse_duration,
+        help = "group the attempts of each peer by periods of this length, e.g. 1h"
+    )]
+    pub bucket: Option<Duration>,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct ServeArgs {
+    #[arg(
+        long,
+        default_value = "127.0.0.1:8080",
+        help = "address the HTTP API listens on"
+    )]
+    pub listen: SocketAddr,
+    #[arg(
+        long,
+        default_value_t = 4,
+        help = "maximum number of handshake requests running at once"
+    )]
+    pub max_requests: usize,
+    #[arg(
+        long,
+        default_value_t = 64,
+        help = "maximum number of targets of a handshake request"
+    )]
+    pub max_targets: usize,
+    #[arg(
+        long,
+        value_parser = humantime::parse_duration,
+        default_value = "60s",
+        help = "time after which a handshake request is abandoned"
+    )]
+    pub request_timeout: Duration,
+    #[arg(
+        long,
+        default_value_t = 1000,
+        help = "number of request results kept for GET /results/{id}"
+    )]
+    pub max_results: usize,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct ReplayArgs {
+    #[arg(required = true, help = "session files recorded with --record")]
+    pub sessions: Vec<PathBuf>,
+}
+
+/// Parse service bits given in hex, as used by the DNS seed `x<services>.` filter
+fn parse_services(s: &str) -> Result<u64, std::num::ParseIntError> {
+    u64::from_str_radix(s.trim_start_matches("0x"), 16)
+}
+
+/// Parse a fork digest given in hex, e.g. `6a95a1a9`
+fn parse_fork_digest(s: &str) -> Result<ForkDigest, String> {
+    let bytes = data_encoding::HEXLOWER_PERMISSIVE
+        .decode(s.trim_start_matches("0x").as_bytes())
+        .map_err(|err| err.to_string())?;
+    bytes.try_into().map_err(|_| "expected 4 bytes".to_string())
 }
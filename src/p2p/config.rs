@@ -1,6 +1,15 @@
 use clap::{command, Parser};
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    time::Duration,
+};
+use tracing_subscriber::filter::Directive;
 
-use crate::p2p::commands::Commands;
+use crate::{
+    p2p::{commands::Commands, connect::Proxy},
+    telemetry::{LogFormat, LogRotation, OtlpConfig, TracingConfig},
+};
 
 /// [`HANDSHAKE_TIMEOUT`] determines the amount of time to wait before determining that a `p2p`
 /// handshake has timed out.
@@ -17,6 +26,154 @@ pub struct Config {
         help = "handshake operation maximum time (in ms)"
     )]
     pub timeout: u64,
+    #[arg(
+        long,
+        global = true,
+        help = "SOCKS5 proxy for the outbound connections, e.g. socks5://127.0.0.1:9050"
+    )]
+    pub proxy: Option<Proxy>,
+    #[arg(
+        long,
+        global = true,
+        help = "local IP address the outbound connections are made from"
+    )]
+    pub bind_addr: Option<IpAddr>,
+    #[arg(
+        long,
+        global = true,
+        help = "network interface the outbound connections are bound to (Linux only)"
+    )]
+    pub interface: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        value_parser = parse_interval,
+        help = "re-run the handshakes at this interval (e.g. 30s, 5m) and only log the peers going up or down"
+    )]
+    pub interval: Option<Duration>,
+    #[arg(
+        long,
+        global = true,
+        help = "serve Prometheus metrics on this address, e.g. 0.0.0.0:9100"
+    )]
+    pub metrics_addr: Option<SocketAddr>,
+    #[arg(
+        long,
+        global = true,
+        help = "record every handshake attempt in this SQLite database"
+    )]
+    pub db: Option<PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        help = "record the messages exchanged in every handshake session to this directory"
+    )]
+    pub record: Option<PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        help = "export the handshake spans over OTLP to this collector, e.g. http://localhost:4317"
+    )]
+    pub otlp_endpoint: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        default_value = "p2p-handshake",
+        help = "service name of the exported traces"
+    )]
+    pub otlp_service_name: String,
+    #[arg(
+        long,
+        global = true,
+        default_value_t = 1.0,
+        value_parser = parse_ratio,
+        help = "fraction of the handshake traces exported (0 to 1)"
+    )]
+    pub otlp_sample_ratio: f64,
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = LogFormat::Text,
+        help = "format of the log lines"
+    )]
+    pub log_format: LogFormat,
+    #[arg(
+        long,
+        global = true,
+        help = "write the logs to this file instead of stderr"
+    )]
+    pub log_file: Option<PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = LogRotation::Daily,
+        help = "how often the log file is rotated"
+    )]
+    pub log_rotation: LogRotation,
+    #[arg(
+        long,
+        global = true,
+        help = "per-target level filter added to RUST_LOG, e.g. p2p_handshake::p2p::btc=debug (repeatable)"
+    )]
+    pub log_filter: Vec<Directive>,
     #[command(subcommand)]
     pub commands: Commands,
 }
+
+impl Config {
+    /// The log and span export settings
+    pub fn tracing(&self) -> TracingConfig {
+        TracingConfig {
+            format: self.log_format,
+            file: self.log_file.clone(),
+            rotation: self.log_rotation,
+            filters: self.log_filter.clone(),
+            otlp: self.otlp_endpoint.clone().map(|endpoint| OtlpConfig {
+                endpoint,
+                service_name: self.otlp_service_name.clone(),
+                sample_ratio: self.otlp_sample_ratio,
+            }),
+        }
+    }
+}
+
+/// Parse a non-zero duration, e.g. `30s`
+fn parse_interval(s: &str) -> Result<Duration, String> {
+    match humantime::parse_duration(s) {
+        Ok(interval) if interval.is_zero() => Err("the interval must not be zero".to_string()),
+        Ok(interval) => Ok(interval),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Parse a fraction between 0 and 1
+fn parse_ratio(s: &str) -> Result<f64, String> {
+    let ratio: f64 = s.parse().map_err(|err| format!("{}", err))?;
+    if !(0.0..=1.0).contains(&ratio) {
+        return Err("expected a fraction between 0 and 1".to_string());
+    }
+    Ok(ratio)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("30s").unwrap(), Duration::from_secs(30));
+        assert!(parse_interval("0s").is_err());
+        assert!(parse_interval("often").is_err());
+    }
+
+    #[test]
+    fn test_parse_ratio() {
+        assert_eq!(parse_ratio("0.25").unwrap(), 0.25);
+        assert_eq!(parse_ratio("1").unwrap(), 1.0);
+        assert!(parse_ratio("1.5").is_err());
+        assert!(parse_ratio("-0.1").is_err());
+        assert!(parse_ratio("NaN").is_err());
+    }
+}
@@ -0,0 +1,430 @@
+use futures::{stream::FuturesUnordered, StreamExt};
+use std::{
+    fmt, io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    str::FromStr,
+    time::Duration,
+};
+use tokio::net::{TcpSocket, TcpStream};
+use tokio_socks::tcp::Socks5Stream;
+use tracing::{instrument, trace};
+
+pub use self::onion::{is_onion, onion_host, onion_pubkey};
+
+mod onion;
+
+/// [`CONNECTION_ATTEMPT_DELAY`] is the delay before racing the next address when connecting to
+/// a hostname, as recommended by [RFC 8305](https://www.rfc-editor.org/rfc/rfc8305#section-5).
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Where to connect: a literal socket address or a hostname resolved at connection time
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Destination {
+    Addr(SocketAddr),
+    Host { host: String, port: u16 },
+}
+
+impl Destination {
+    /// The socket address of the destination, if it is not a hostname
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Destination::Addr(addr) => Some(*addr),
+            Destination::Host { .. } => None,
+        }
+    }
+
+    /// The socket address to announce to the peer, unspecified for hostnames
+    pub fn announced_addr(&self) -> SocketAddr {
+        match self {
+            Destination::Addr(addr) => *addr,
+            Destination::Host { port, .. } => (Ipv4Addr::UNSPECIFIED, *port).into(),
+        }
+    }
+
+    /// Name of the network the destination was reached over, reported with the handshake outcome
+    pub fn family(&self) -> &'static str {
+        match self {
+            Destination::Addr(addr) => family(&addr.ip()),
+            Destination::Host { host, .. } if is_onion(host) => "Tor",
+            Destination::Host { .. } => "proxy",
+        }
+    }
+}
+
+impl From<SocketAddr> for Destination {
+    fn from(addr: SocketAddr) -> Self {
+        Destination::Addr(addr)
+    }
+}
+
+impl FromStr for Destination {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(addr) = s.parse() {
+            return Ok(Destination::Addr(addr));
+        }
+
+        let (host, port) = s
+            .rsplit_once(':')
+            .ok_or("expected <ip_address:port> or <hostname:port>")?;
+        if host.is_empty() || host.contains(':') {
+            return Err(format!("invalid hostname: {host}"));
+        }
+        if is_onion(host) && onion_pubkey(host).is_none() {
+            return Err(format!("invalid Tor v3 onion address: {host}"));
+        }
+        Ok(Destination::Host {
+            host: host.to_ascii_lowercase(),
+            port: port.parse().map_err(|_| "invalid port")?,
+        })
+    }
+}
+
+impl fmt::Display for Destination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Destination::Addr(addr) => write!(f, "{}", addr),
+            Destination::Host { host, port } => write!(f, "{}:{}", host, port),
+        }
+    }
+}
+
+/// A SOCKS5 proxy given as `socks5://<host>:<port>`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Proxy(pub Destination);
+
+impl FromStr for Proxy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let addr = s
+            .strip_prefix("socks5://")
+            .or_else(|| s.strip_prefix("socks5h://"))
+            .ok_or("expected socks5://<host>:<port>")?;
+        addr.parse().map(Proxy)
+    }
+}
+
+impl fmt::Display for Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "socks5://{}", self.0)
+    }
+}
+
+/// The two ends of an established connection
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Endpoints {
+    /// The local address the connection was made from
+    pub local: SocketAddr,
+    /// The address connected to, or the requested destination when going through a proxy
+    pub remote: Destination,
+}
+
+/// An established connection and the peer it reached
+#[derive(Debug)]
+pub struct Connection {
+    pub stream: TcpStream,
+    pub endpoints: Endpoints,
+}
+
+/// Opens the outbound connections, directly or through a SOCKS5 proxy
+#[derive(Clone, Debug, Default)]
+pub struct Connector {
+    pub proxy: Option<Proxy>,
+    /// Local address the sockets are bound to
+    pub bind_addr: Option<IpAddr>,
+    /// Network interface the sockets are bound to (`SO_BINDTODEVICE`)
+    pub interface: Option<String>,
+}
+
+impl Connector {
+    /// Connect to the destination.
+    ///
+    /// Through a proxy, hostnames are sent to it unresolved (remote DNS), which is what allows
+    /// reaching onion services over Tor.
+    #[instrument(level = "trace", skip_all, fields(destination=&*format!("{}", destination)))]
+    pub async fn connect(&self, destination: &Destination) -> io::Result<Connection> {
+        let Some(Proxy(proxy)) = &self.proxy else {
+            let stream = self.connect_direct(destination).await?;
+            let endpoints = Endpoints {
+                local: stream.local_addr()?,
+                remote: Destination::Addr(stream.peer_addr()?),
+            };
+            return Ok(Connection { stream, endpoints });
+        };
+
+        trace!("connecting through proxy {} ...", proxy);
+        let socket = self.connect_direct(proxy).await?;
+        let local = socket.local_addr()?;
+        let res = match destination {
+            Destination::Addr(addr) => Socks5Stream::connect_with_socket(socket, *addr).await,
+            Destination::Host { host, port } => {
+                Socks5Stream::connect_with_socket(socket, (host.as_str(), *port)).await
+            }
+        };
+        let stream = res
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, format!("socks5: {err}")))?
+            .into_inner();
+
+        Ok(Connection {
+            stream,
+            endpoints: Endpoints {
+                local,
+                remote: destination.clone(),
+            },
+        })
+    }
+
+    /// Connect to the destination without going through the proxy.
+    ///
+    /// Hostnames are resolved to both their IPv6 and IPv4 addresses and connected to with
+    /// Happy Eyeballs: attempts alternate between the families, starting with IPv6, and a new
+    /// attempt starts every [`CONNECTION_ATTEMPT_DELAY`] until the first one succeeds.
+    async fn connect_direct(&self, destination: &Destination) -> io::Result<TcpStream> {
+        match destination {
+            Destination::Addr(addr) => self.attempt(*addr).await,
+            Destination::Host { host, .. } if is_onion(host) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "onion addresses can only be reached through a proxy",
+            )),
+            Destination::Host { host, port } => {
+                let mut addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), *port))
+                    .await?
+                    .collect();
+                trace!("resolved {} addresses", addrs.len());
+                // A socket bound to an address can only reach the addresses of its family
+                if let Some(bind_addr) = self.bind_addr {
+                    addrs.retain(|addr| addr.is_ipv4() == bind_addr.is_ipv4());
+                }
+                self.race(addrs).await
+            }
+        }
+    }
+
+    /// Race TCP connections to the addresses, returning the first one established
+    pub async fn race(&self, addrs: Vec<SocketAddr>) -> io::Result<TcpStream> {
+        let mut pending = interleave(addrs).into_iter();
+        let mut attempts = FuturesUnordered::new();
+        match pending.next() {
+            Some(addr) => attempts.push(self.attempt(addr)),
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrNotAvailable,
+                    "no address to connect to",
+                ))
+            }
+        }
+
+        loop {
+            tokio::select! {
+                res = attempts.next(), if !attempts.is_empty() => {
+                    match res.expect("attempts is not empty") {
+                        Ok(stream) => return Ok(stream),
+                        Err(err) => {
+                            // A failed attempt starts the next one right away
+                            match pending.next() {
+                                Some(addr) => attempts.push(self.attempt(addr)),
+                                None if attempts.is_empty() => return Err(err),
+                                None => (),
+                            }
+                        }
+                    }
+                }
+                _ = tokio::time::sleep(CONNECTION_ATTEMPT_DELAY), if pending.len() > 0 => {
+                    attempts.push(self.attempt(pending.next().expect("pending is not empty")));
+                }
+            }
+        }
+    }
+
+    async fn attempt(&self, addr: SocketAddr) -> io::Result<TcpStream> {
+        trace!("connecting to {:?} ...", addr);
+        let res = match self.socket(&addr) {
+            Ok(socket) => socket.connect(addr).await,
+            Err(err) => Err(err),
+        };
+        res.map_err(|err| {
+            trace!(?err, "failed to connect to {:?}", addr);
+            err
+        })
+    }
+
+    /// Create a socket for connecting to `addr`, bound to the local address and interface
+    fn socket(&self, addr: &SocketAddr) -> io::Result<TcpSocket> {
+        let socket = match addr {
+            SocketAddr::V4(_) => TcpSocket::new_v4()?,
+            SocketAddr::V6(_) => TcpSocket::new_v6()?,
+        };
+        if let Some(interface) = &self.interface {
+            bind_device(&socket, interface)?;
+        }
+        if let Some(bind_addr) = self.bind_addr {
+            socket.bind((bind_addr, 0).into())?;
+        }
+        Ok(socket)
+    }
+}
+
+#[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+fn bind_device(socket: &TcpSocket, interface: &str) -> io::Result<()> {
+    socket.bind_device(Some(interface.as_bytes()))
+}
+
+#[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+fn bind_device(_socket: &TcpSocket, _interface: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "binding to an interface is not supported on this platform",
+    ))
+}
+
+/// Order the addresses alternating between IPv6 and IPv4, starting with IPv6
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let (v6, v4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(SocketAddr::is_ipv6);
+    let mut v6 = v6.into_iter();
+    let mut v4 = v4.into_iter();
+    let mut ordered = Vec::with_capacity(v6.len() + v4.len());
+    loop {
+        match (v6.next(), v4.next()) {
+            (None, None) => return ordered,
+            (a, b) => ordered.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+/// Name of the address family of `ip`
+fn family(ip: &IpAddr) -> &'static str {
+    match ip {
+        IpAddr::V4(_) => "IPv4",
+        IpAddr::V6(_) => "IPv6",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    #[test]
+    fn test_interleave() {
+        let addrs: Vec<SocketAddr> = vec![
+            "10.0.0.1:8333".parse().unwrap(),
+            "10.0.0.2:8333".parse().unwrap(),
+            "10.0.0.3:8333".parse().unwrap(),
+            "[2001:db8::1]:8333".parse().unwrap(),
+        ];
+
+        assert_eq!(
+            interleave(addrs.clone()),
+            vec![addrs[3], addrs[0], addrs[1], addrs[2]]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_race_skips_unreachable_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let reachable = listener.local_addr().unwrap();
+
+        // Nothing listens on the port of a dropped listener
+        let unreachable = TcpListener::bind("[::1]:0")
+            .await
+            .map(|listener| listener.local_addr().unwrap())
+            .unwrap_or("[::1]:1".parse().unwrap());
+
+        // Verify that the IPv4 address wins once the IPv6 attempt failed
+        let stream = Connector::default()
+            .race(vec![reachable, unreachable])
+            .await
+            .unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), reachable);
+    }
+
+    #[tokio::test]
+    async fn test_connect_through_socks5_proxy() {
+        let onion = format!("{}:8333", onion_host(&[7; 32]));
+        let destination: Destination = onion.parse().unwrap();
+
+        // Create a SOCKS5 stand-in which expects the hostname unresolved and echoes the payload
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy: Proxy = format!("socks5://{}", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let expected = destination.clone();
+        let handle = tokio::spawn(async move {
+            let (mut incoming, _) = listener.accept().await.unwrap();
+            let mut greeting = [0u8; 3];
+            incoming.read_exact(&mut greeting).await.unwrap();
+            assert_eq!(greeting, [5, 1, 0]);
+            incoming.write_all(&[5, 0]).await.unwrap();
+
+            // CONNECT request with a domain name address type
+            let mut request = [0u8; 5];
+            incoming.read_exact(&mut request).await.unwrap();
+            assert_eq!(request[..4], [5, 1, 0, 3]);
+            let mut host = vec![0u8; request[4] as usize];
+            incoming.read_exact(&mut host).await.unwrap();
+            let port = incoming.read_u16().await.unwrap();
+            assert_eq!(
+                Destination::Host {
+                    host: String::from_utf8(host).unwrap(),
+                    port
+                },
+                expected
+            );
+            incoming
+                .write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0])
+                .await
+                .unwrap();
+
+            let mut payload = [0u8; 4];
+            incoming.read_exact(&mut payload).await.unwrap();
+            incoming.write_all(&payload).await.unwrap();
+        });
+
+        // Verify that onion destinations are only reachable through the proxy
+        assert!(Connector::default().connect(&destination).await.is_err());
+
+        let connector = Connector {
+            proxy: Some(proxy),
+            ..Default::default()
+        };
+        let mut connection = connector.connect(&destination).await.unwrap();
+        assert_eq!(connection.endpoints.remote, destination);
+        assert_eq!(connection.endpoints.remote.family(), "Tor");
+
+        connection.stream.write_all(b"ping").await.unwrap();
+        let mut payload = [0u8; 4];
+        connection.stream.read_exact(&mut payload).await.unwrap();
+        assert_eq!(&payload, b"ping");
+
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_connect_from_bind_addr() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let destination = Destination::Addr(listener.local_addr().unwrap());
+
+        // Verify that the connection is made from the bound address and reported as such
+        let connector = Connector {
+            bind_addr: Some("127.0.0.2".parse().unwrap()),
+            ..Default::default()
+        };
+        let connection = connector.connect(&destination).await.unwrap();
+        let (_, from) = listener.accept().await.unwrap();
+        assert_eq!(connection.endpoints.local, from);
+        assert_eq!(from.ip(), connector.bind_addr.unwrap());
+        assert_eq!(connection.endpoints.remote, destination);
+
+        // Verify that an address the host does not own cannot be bound
+        let connector = Connector {
+            bind_addr: Some("192.0.2.1".parse().unwrap()),
+            ..Default::default()
+        };
+        assert!(connector.connect(&destination).await.is_err());
+    }
+}
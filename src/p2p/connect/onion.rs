@@ -0,0 +1,63 @@
+use data_encoding::BASE32_NOPAD;
+use sha3::{Digest, Sha3_256};
+
+/// [`ONION_VERSION`] is the version byte of Tor v3 onion addresses.
+const ONION_VERSION: u8 = 3;
+
+/// Build the `<base32>.onion` hostname of a Tor v3 service from its ed25519 public key
+pub fn onion_host(pubkey: &[u8; 32]) -> String {
+    let mut address = Vec::with_capacity(35);
+    address.extend_from_slice(pubkey);
+    address.extend_from_slice(&checksum(pubkey));
+    address.push(ONION_VERSION);
+    format!(
+        "{}.onion",
+        BASE32_NOPAD.encode(&address).to_ascii_lowercase()
+    )
+}
+
+/// Decode the public key of a Tor v3 onion hostname, checking its version and checksum
+pub fn onion_pubkey(host: &str) -> Option<[u8; 32]> {
+    let encoded = host.strip_suffix(".onion")?.to_ascii_uppercase();
+    let address = BASE32_NOPAD.decode(encoded.as_bytes()).ok()?;
+    if address.len() != 35 || address[34] != ONION_VERSION {
+        return None;
+    }
+    let pubkey: [u8; 32] = address[..32].try_into().ok()?;
+    (address[32..34] == checksum(&pubkey)).then_some(pubkey)
+}
+
+/// Whether `host` is an onion service name, of any version
+pub fn is_onion(host: &str) -> bool {
+    host.to_ascii_lowercase().ends_with(".onion")
+}
+
+fn checksum(pubkey: &[u8; 32]) -> [u8; 2] {
+    let digest = Sha3_256::new()
+        .chain_update(b".onion checksum")
+        .chain_update(pubkey)
+        .chain_update([ONION_VERSION])
+        .finalize();
+    [digest[0], digest[1]]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The onion address of the Tor Project website
+    const HOST: &str = "2gzyxa5ihm7nsggfxnu52rck2vv4rvmdlkiu3zzui5du4xyclen53wid.onion";
+
+    #[test]
+    fn test_onion_roundtrip() {
+        let pubkey = onion_pubkey(HOST).unwrap();
+        assert_eq!(onion_host(&pubkey), HOST);
+        assert!(is_onion(HOST));
+
+        // Verify that a corrupted checksum is rejected
+        let tampered = HOST.replacen("2gzy", "2gzz", 1);
+        assert!(onion_pubkey(&tampered).is_none());
+        // Verify that v2 addresses are rejected
+        assert!(onion_pubkey("expyuzz4wqqyqhjn.onion").is_none());
+    }
+}
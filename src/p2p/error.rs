@@ -1,3 +1,5 @@
+use libp2p::PeerId;
+use multistream_select::NegotiationError;
 use reth_ecies::ECIESError;
 use reth_eth_wire::errors::P2PStreamError;
 use std::fmt::Display;
@@ -14,12 +16,148 @@ pub enum P2PError {
     TokioElapsedError(#[from] tokio::time::error::Elapsed),
     #[error("{0}: P2P stream error")]
     P2PStreamError(#[from] P2PStreamError),
+    #[error("{0}: discv5 error")]
+    Discv5Error(#[from] Discv5Error),
+    #[error("{0}: DNS discovery error")]
+    DnsError(#[from] DnsError),
+    #[error("{0}: libp2p error")]
+    Libp2pError(#[from] Libp2pError),
+    #[error("{0}: beacon error")]
+    BeaconError(#[from] BeaconError),
+    #[error("{0}: lightning error")]
+    LnError(#[from] LnError),
+}
+
+impl P2PError {
+    /// Short name of the error variant, used as a metrics label
+    pub fn kind(&self) -> &'static str {
+        match self {
+            P2PError::P2PHandshakeError(err) => err.kind,
+            P2PError::ECIESError(_) => "ecies",
+            P2PError::IOError(_) => "io",
+            P2PError::TokioElapsedError(_) => "timeout",
+            P2PError::P2PStreamError(_) => "p2p_stream",
+            P2PError::Discv5Error(_) => "discv5",
+            P2PError::DnsError(_) => "dns",
+            P2PError::Libp2pError(_) => "libp2p",
+            P2PError::BeaconError(_) => "beacon",
+            P2PError::LnError(_) => "ln",
+        }
+    }
+
+    /// The error without the target it failed with
+    pub fn message(&self) -> String {
+        match self {
+            P2PError::P2PHandshakeError(err) => err.message().to_string(),
+            err => err.to_string(),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Discv4Error {
+    #[error("packet too short")]
+    PacketTooShort,
+    #[error("packet hash mismatch")]
+    HashMismatch,
+    #[error("unknown message type {0:#04x}")]
+    UnknownMessage(u8),
+    #[error("timed out waiting for {0}")]
+    Timeout(&'static str),
+    #[error("{0}: invalid signature")]
+    Secp256k1(#[from] secp256k1::Error),
+    #[error("{0}: RLP decoding error")]
+    Rlp(#[from] alloy_rlp::Error),
+    #[error("{0}: IO error")]
+    IOError(#[from] std::io::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum DnsError {
+    #[error("invalid tree entry: {0}")]
+    InvalidEntry(String),
+    #[error("invalid ENR: {0}")]
+    InvalidEnr(String),
+    #[error("invalid root signature")]
+    InvalidSignature,
+    #[error("missing TXT record for {0}")]
+    MissingEntry(String),
+    #[error("entry hash mismatch for {0}")]
+    HashMismatch(String),
+    #[error("too many entries in tree {0}")]
+    TreeTooLarge(String),
+    #[error("{0}: invalid key")]
+    Secp256k1(#[from] secp256k1::Error),
+    #[error("{0}: resolve error")]
+    Resolve(#[from] std::io::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Discv5Error {
+    #[error("{0}: invalid local ENR")]
+    Enr(#[from] discv5::enr::EnrError),
+    #[error("invalid configuration: {0}")]
+    Config(&'static str),
+    // `discv5::Error` does not implement `std::error::Error`, so it can't be a source
+    #[error("failed to start the service: {0}")]
+    Start(discv5::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Libp2pError {
+    #[error("unsupported multiaddr {0}, expected /ip4|ip6|dns/<host>/tcp/<port>[/p2p/<peer id>]")]
+    InvalidAddress(String),
+    #[error("peer id mismatch: expected {expected}, got {actual}")]
+    PeerIdMismatch { expected: PeerId, actual: PeerId },
+    #[error("{0}: noise error")]
+    Noise(String),
+    #[error("{0}: yamux error")]
+    Yamux(String),
+    #[error("{0}: protocol negotiation error")]
+    Negotiation(#[from] NegotiationError),
+    #[error("invalid identify message: {0}")]
+    InvalidIdentify(&'static str),
+    #[error("{0}: IO error")]
+    IOError(#[from] std::io::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum BeaconError {
+    #[error("{0}")]
+    Libp2p(#[from] Libp2pError),
+    #[error("request refused with code {code}: {message}")]
+    Refused { code: u8, message: String },
+    #[error("invalid response: {0}")]
+    InvalidResponse(&'static str),
+    #[error("unexpected fork digest {digest} for {network}")]
+    ForkDigestMismatch { digest: String, network: String },
+    #[error("{0}: IO error")]
+    IOError(#[from] std::io::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum LnError {
+    #[error("unsupported handshake version {0} in act {1}")]
+    InvalidVersion(u8, u8),
+    #[error("message authentication failed")]
+    Decryption,
+    #[error("message too large: {0} bytes")]
+    MessageTooLarge(usize),
+    #[error("invalid message: {0}")]
+    InvalidMessage(&'static str),
+    #[error("expected init, got message type {0}")]
+    UnexpectedMessage(u16),
+    #[error("{0}: invalid key")]
+    Secp256k1(#[from] secp256k1::Error),
+    #[error("{0}: IO error")]
+    IOError(#[from] std::io::Error),
 }
 
 #[derive(Debug)]
 pub struct P2PHandshake {
     message: String,
     address: String,
+    kind: &'static str,
 }
 
 impl P2PHandshake {
@@ -27,8 +165,19 @@ impl P2PHandshake {
         Self {
             message: err.to_string(),
             address,
+            kind: err.kind(),
         }
     }
+
+    /// The target the handshake failed with
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// The error which made the handshake fail
+    pub fn message(&self) -> &str {
+        &self.message
+    }
 }
 
 impl Display for P2PHandshake {
@@ -2,52 +2,116 @@ use measure_time::{debug_time, info_time};
 use reth_ecies::stream::ECIESStream;
 use reth_primitives::NodeRecord;
 use secp256k1::SecretKey;
-use std::{net::IpAddr, time::Duration};
-use tokio::net::TcpStream;
-use tracing::instrument;
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use tracing::{instrument, trace_span, Instrument};
 
-use crate::p2p::{error::P2PError, eth::utils::create_hello_msg};
+use crate::{
+    p2p::{
+        connect::{Connector, Destination, Endpoints},
+        error::P2PError,
+        eth::utils::create_hello_msg,
+        record::{Recorder, Tap},
+    },
+    telemetry::HandshakeMetrics,
+};
 
 mod constants;
-mod stream;
+pub mod crawl;
+pub mod discv4;
+pub mod discv5;
+pub mod dns;
+pub mod enr;
+#[cfg(feature = "test-utils")]
+pub mod mock;
+pub mod stream;
+pub mod target;
 mod utils;
 
 pub struct Config {
     pub timeout: u64,
     pub peer: NodeRecord,
+    /// Hostname to connect to instead of the address of the peer record
+    pub host: Option<String>,
+    pub connector: Connector,
+    /// Directory the session is recorded to
+    pub record: Option<PathBuf>,
+}
+
+impl Config {
+    /// The destination of the RLPx connection
+    pub fn destination(&self) -> Destination {
+        match &self.host {
+            Some(host) => Destination::Host {
+                host: host.clone(),
+                port: self.peer.tcp_port,
+            },
+            None => Destination::Addr(self.peer.tcp_addr()),
+        }
+    }
 }
 
 /// Perform a P2P handshake with a peer
-#[instrument(level = "trace", skip_all, fields(peer=&*format!("{:?}", config.peer.address)))]
-pub async fn handshake(config: Config) -> Result<IpAddr, P2PError> {
-    info_time!("[{:?}] Perform a P2P handshake", config.peer.address);
+#[instrument(level = "trace", skip_all, fields(peer=&*format!("{}", config.destination())))]
+pub async fn handshake(config: Config) -> Result<Endpoints, P2PError> {
+    let destination = config.destination();
+    info_time!("[{}] Perform a P2P handshake", destination);
+
+    let recorder = config.record.as_ref().map(|_| Recorder::default());
+    let res = exchange(&config, &destination, recorder.clone()).await;
+    if let (Some(dir), Some(recorder)) = (&config.record, recorder) {
+        let error = res.as_ref().err().map(P2PError::message);
+        recorder.save(dir, "eth", destination.to_string(), error);
+    }
+    res
+}
 
+/// Connect to the peer and perform the ECIES and Hello handshakes
+async fn exchange(
+    config: &Config,
+    destination: &Destination,
+    recorder: Option<Recorder>,
+) -> Result<Endpoints, P2PError> {
+    let metrics = HandshakeMetrics::new("eth");
     let key = SecretKey::new(&mut rand::thread_rng());
-    let ecies_stream = {
-        debug_time!(
-            "[{:?}] Send and Parse the ECIES auth message",
-            config.peer.address
-        );
+    let (ecies_stream, endpoints) = {
+        debug_time!("[{}] Send and Parse the ECIES auth message", destination);
 
         // Connect to the peer and perform the ECIES handshake
+        let started = Instant::now();
         let outgoing = tokio::time::timeout(
             Duration::from_millis(config.timeout),
-            TcpStream::connect((config.peer.address, config.peer.tcp_port)),
+            config.connector.connect(destination),
+        )
+        .await??;
+        metrics.phase("connect", started.elapsed());
+
+        let started = Instant::now();
+        let ecies_stream = tokio::time::timeout(
+            Duration::from_millis(config.timeout),
+            ECIESStream::connect(outgoing.stream, key, config.peer.id)
+                .instrument(trace_span!("ecies")),
         )
         .await??;
-        ECIESStream::connect(outgoing, key, config.peer.id).await?
+        metrics.phase("ecies", started.elapsed());
+        (ecies_stream, outgoing.endpoints)
     };
     {
         // Send, Parse the P2P Hello message and perform the initial handshake
         debug_time!(
-            "[{:?}] Send, Parse the P2P Hello message and perform the initial handshake",
-            config.peer.address
+            "[{}] Send, Parse the P2P Hello message and perform the initial handshake",
+            destination
         );
+        let started = Instant::now();
         let hello_msg = create_hello_msg(key);
-        stream::P2PStream::new(ecies_stream)
+        stream::P2PStream::new(Tap::new(ecies_stream, recorder))
             .handshake(hello_msg, config.timeout)
+            .instrument(trace_span!("hello"))
             .await?;
+        metrics.phase("hello", started.elapsed());
     }
 
-    Ok(config.peer.address)
+    Ok(endpoints)
 }
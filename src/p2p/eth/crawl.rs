@@ -0,0 +1,90 @@
+use futures::{stream, StreamExt};
+use reth_ecies::util::pk2id;
+use reth_primitives::{NodeRecord, PeerId};
+use secp256k1::{SecretKey, SECP256K1};
+use std::{collections::HashSet, time::Duration};
+use tracing::{debug, instrument};
+
+use crate::p2p::{error::Discv4Error, eth::discv4::Discv4Session};
+
+#[derive(Debug)]
+pub struct CrawlConfig {
+    pub bootnodes: Vec<NodeRecord>,
+    pub timeout: u64,
+    pub max_depth: usize,
+    pub max_nodes: usize,
+    pub concurrency: usize,
+}
+
+/// Walk the discovery v4 Kademlia table starting from the bootnodes.
+///
+/// Every known node is asked for its neighbours of a random target, and the newly discovered
+/// nodes are queried in turn until `max_nodes` known nodes is reached. As with the bitcoin
+/// crawl, the nodes up to `max_depth` hops from the bootnodes are returned, the farthest ones
+/// without being asked for their own neighbours. Returns every node which advertises a TCP
+/// endpoint.
+#[instrument(level = "trace", skip_all)]
+pub async fn crawl(config: CrawlConfig) -> Vec<NodeRecord> {
+    let config = &config;
+    let key = SecretKey::new(&mut rand::thread_rng());
+
+    let mut seen: HashSet<PeerId> = HashSet::new();
+    let mut known: Vec<NodeRecord> = Vec::new();
+    let mut frontier: Vec<NodeRecord> = Vec::new();
+    for node in &config.bootnodes {
+        if seen.insert(node.id) {
+            known.push(*node);
+            frontier.push(*node);
+        }
+    }
+
+    // The nodes at `depth` hops are discovered by querying the ones at `depth - 1`
+    for depth in 1..=config.max_depth {
+        if frontier.is_empty() || known.len() >= config.max_nodes {
+            break;
+        }
+        debug!("querying {} nodes at depth {}", frontier.len(), depth - 1);
+
+        let results: Vec<_> = stream::iter(std::mem::take(&mut frontier))
+            .map(|node| async move { (node, lookup(key, node, config).await) })
+            .buffer_unordered(config.concurrency.max(1))
+            .collect()
+            .await;
+
+        for (node, result) in results {
+            let neighbours = match result {
+                Ok(neighbours) => neighbours,
+                Err(err) => {
+                    debug!("[unreachable] [{:?}] error: {}", node.udp_addr(), err);
+                    continue;
+                }
+            };
+
+            for neighbour in neighbours {
+                if known.len() >= config.max_nodes {
+                    break;
+                }
+                if seen.insert(neighbour.id) {
+                    known.push(neighbour);
+                    frontier.push(neighbour);
+                }
+            }
+        }
+    }
+
+    known.retain(|node| node.tcp_port != 0);
+    known
+}
+
+/// Bond with a node and ask it for the neighbours of a random target
+async fn lookup(
+    key: SecretKey,
+    node: NodeRecord,
+    config: &CrawlConfig,
+) -> Result<Vec<NodeRecord>, Discv4Error> {
+    let session = Discv4Session::connect(key, node, Duration::from_millis(config.timeout)).await?;
+    session.bond().await?;
+
+    let target = pk2id(&SecretKey::new(&mut rand::thread_rng()).public_key(SECP256K1));
+    session.find_node(target).await
+}
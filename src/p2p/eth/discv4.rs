@@ -0,0 +1,272 @@
+use reth_primitives::{NodeRecord, PeerId, B256};
+use secp256k1::SecretKey;
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+use tokio::{net::UdpSocket, time::Instant};
+use tracing::{instrument, trace};
+
+use self::proto::{Endpoint, FindNode, Message, Packet, Ping, Pong, MAX_PACKET_SIZE};
+use crate::p2p::error::Discv4Error;
+
+mod proto;
+
+/// [`MAX_NEIGHBOURS`] is the number of nodes returned for a `FindNode` request (the Kademlia
+/// bucket size).
+const MAX_NEIGHBOURS: usize = 16;
+
+/// [`MAX_NEIGHBOURS_PACKETS`] is the number of `Neighbours` packets a full reply is split into.
+const MAX_NEIGHBOURS_PACKETS: usize = 2;
+
+/// [`NEIGHBOURS_IDLE`] is how long to wait for the rest of a reply once its first packet arrived.
+const NEIGHBOURS_IDLE: Duration = Duration::from_millis(200);
+
+/// [`PING_GRACE`] is how long to wait for the ping of the remote once it answered ours.
+const PING_GRACE: Duration = Duration::from_millis(200);
+
+/// A discovery v4 session with a single remote node over a dedicated UDP socket.
+///
+/// Using one socket per remote keeps request/response matching trivial: every packet received
+/// on the socket comes from the node we are talking to.
+#[derive(Debug)]
+pub struct Discv4Session {
+    socket: UdpSocket,
+    key: SecretKey,
+    remote: NodeRecord,
+    timeout: Duration,
+}
+
+impl Discv4Session {
+    /// Bind a local UDP socket for talking to the remote node
+    pub async fn connect(
+        key: SecretKey,
+        remote: NodeRecord,
+        timeout: Duration,
+    ) -> Result<Self, Discv4Error> {
+        let local: SocketAddr = if remote.address.is_ipv4() {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        } else {
+            (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(remote.udp_addr()).await?;
+
+        Ok(Self {
+            socket,
+            key,
+            remote,
+            timeout,
+        })
+    }
+
+    /// Ping the remote node and answer its ping back, so both sides hold an endpoint proof.
+    ///
+    /// Nodes only answer `FindNode` requests from peers which have recently answered their ping.
+    #[instrument(level = "trace", skip_all, fields(peer=&*format!("{:?}", self.remote.udp_addr())))]
+    pub async fn bond(&self) -> Result<(), Discv4Error> {
+        let local = Endpoint::new(self.socket.local_addr()?, 0);
+        let remote = Endpoint::new(self.remote.udp_addr(), self.remote.tcp_port);
+
+        trace!("sending ping ...");
+        let ping_hash = self.send(Message::Ping(Ping::new(local, remote))).await?;
+
+        let mut deadline = Instant::now() + self.timeout;
+        let mut pong_received = false;
+        let mut ping_answered = false;
+        while !(pong_received && ping_answered) {
+            let Some(packet) = self.recv(deadline).await? else {
+                break;
+            };
+            match packet.msg {
+                Message::Pong(pong) if pong.ping_hash == ping_hash => {
+                    trace!("received pong");
+                    pong_received = true;
+                    deadline = deadline.min(Instant::now() + PING_GRACE);
+                }
+                Message::Ping(_) => {
+                    self.answer_ping(packet.hash).await?;
+                    ping_answered = true;
+                }
+                msg => trace!(?msg, "received unexpected message while bonding"),
+            }
+        }
+
+        // The remote may already hold a proof for us and skip pinging back
+        if pong_received {
+            Ok(())
+        } else {
+            Err(Discv4Error::Timeout("pong"))
+        }
+    }
+
+    /// Ask the remote node for the nodes closest to `target` in its routing table
+    #[instrument(level = "trace", skip_all, fields(peer=&*format!("{:?}", self.remote.udp_addr())))]
+    pub async fn find_node(&self, target: PeerId) -> Result<Vec<NodeRecord>, Discv4Error> {
+        trace!("sending findnode ...");
+        self.send(Message::FindNode(FindNode::new(target))).await?;
+
+        // The reply is split over several `Neighbours` packets to fit in the packet size limit,
+        // and a node knowing less than a bucket of nodes sends a single one
+        let mut deadline = Instant::now() + self.timeout;
+        let mut nodes = Vec::new();
+        let mut packets = 0;
+        while nodes.len() < MAX_NEIGHBOURS && packets < MAX_NEIGHBOURS_PACKETS {
+            let Some(packet) = self.recv(deadline).await? else {
+                break;
+            };
+            match packet.msg {
+                Message::Neighbours(neighbours) => {
+                    trace!("received {} neighbours", neighbours.nodes.len());
+                    nodes.extend(neighbours.nodes);
+                    packets += 1;
+                    deadline = deadline.min(Instant::now() + NEIGHBOURS_IDLE);
+                }
+                Message::Ping(_) => self.answer_ping(packet.hash).await?,
+                msg => trace!(
+                    ?msg,
+                    "received unexpected message while waiting for neighbours"
+                ),
+            }
+        }
+
+        Ok(nodes)
+    }
+
+    async fn answer_ping(&self, ping_hash: B256) -> Result<(), Discv4Error> {
+        trace!("received ping, sending pong ...");
+        let to = Endpoint::new(self.remote.udp_addr(), self.remote.tcp_port);
+        self.send(Message::Pong(Pong::new(to, ping_hash))).await?;
+        Ok(())
+    }
+
+    async fn send(&self, msg: Message) -> Result<B256, Discv4Error> {
+        let (packet, hash) = msg.encode(&self.key);
+        self.socket.send(&packet).await?;
+        Ok(hash)
+    }
+
+    /// Receive the next valid packet signed by the remote node, or `None` once the deadline passes
+    async fn recv(&self, deadline: Instant) -> Result<Option<Packet>, Discv4Error> {
+        let mut buf = [0u8; MAX_PACKET_SIZE];
+        loop {
+            let len = match tokio::time::timeout_at(deadline, self.socket.recv(&mut buf)).await {
+                Ok(len) => len?,
+                Err(_) => return Ok(None),
+            };
+            match Packet::decode(&buf[..len]) {
+                Ok(packet) if packet.node_id == self.remote.id => return Ok(Some(packet)),
+                Ok(packet) => {
+                    trace!(node_id=?packet.node_id, "ignoring packet from unexpected node")
+                }
+                Err(err) => trace!(?err, "ignoring invalid packet"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::p2p::eth::discv4::proto::Neighbours;
+    use reth_ecies::util::pk2id;
+    use secp256k1::SECP256K1;
+
+    #[tokio::test]
+    async fn test_bond_and_find_node() {
+        // Create a discovery responder which requires an endpoint proof before answering findnode
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_key = SecretKey::new(&mut rand::thread_rng());
+        let server_addr = socket.local_addr().unwrap();
+        let server = NodeRecord::new(server_addr, pk2id(&server_key.public_key(SECP256K1)));
+        let neighbour = NodeRecord::new(
+            "10.0.0.1:30303".parse().unwrap(),
+            pk2id(&SecretKey::new(&mut rand::thread_rng()).public_key(SECP256K1)),
+        );
+
+        let handle = tokio::spawn(async move {
+            let mut buf = [0u8; MAX_PACKET_SIZE];
+            let mut bonded = false;
+            loop {
+                let (len, from) = socket.recv_from(&mut buf).await.unwrap();
+                let packet = Packet::decode(&buf[..len]).unwrap();
+                let reply = match packet.msg {
+                    Message::Ping(_) => {
+                        // Answer the ping and ping back to verify the client endpoint
+                        let (pong, _) =
+                            Message::Pong(Pong::new(Endpoint::new(from, 0), packet.hash))
+                                .encode(&server_key);
+                        socket.send_to(&pong, from).await.unwrap();
+                        let local = Endpoint::new(server_addr, server_addr.port());
+                        Message::Ping(Ping::new(local, Endpoint::new(from, 0)))
+                    }
+                    Message::Pong(_) => {
+                        bonded = true;
+                        continue;
+                    }
+                    Message::FindNode(_) => {
+                        assert!(bonded, "findnode received before the endpoint proof");
+                        Message::Neighbours(Neighbours {
+                            nodes: vec![neighbour],
+                            expire: u64::MAX,
+                        })
+                    }
+                    Message::Neighbours(_) => panic!("unexpected neighbours"),
+                };
+                let (packet, _) = reply.encode(&server_key);
+                socket.send_to(&packet, from).await.unwrap();
+                if matches!(reply, Message::Neighbours(_)) {
+                    break;
+                }
+            }
+        });
+
+        let key = SecretKey::new(&mut rand::thread_rng());
+        let session = Discv4Session::connect(key, server, Duration::from_millis(500))
+            .await
+            .unwrap();
+        session.bond().await.unwrap();
+        let started = Instant::now();
+        let nodes = session.find_node(PeerId::repeat_byte(0x11)).await.unwrap();
+
+        // Verify that the neighbours of the responder were returned, without waiting for a full
+        // bucket until the timeout
+        assert_eq!(nodes, vec![neighbour]);
+        assert!(started.elapsed() < Duration::from_millis(500));
+
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_bond_without_ping_back() {
+        // Create a discovery responder which already holds an endpoint proof and never pings back
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_key = SecretKey::new(&mut rand::thread_rng());
+        let server = NodeRecord::new(
+            socket.local_addr().unwrap(),
+            pk2id(&server_key.public_key(SECP256K1)),
+        );
+
+        let handle = tokio::spawn(async move {
+            let mut buf = [0u8; MAX_PACKET_SIZE];
+            let (len, from) = socket.recv_from(&mut buf).await.unwrap();
+            let packet = Packet::decode(&buf[..len]).unwrap();
+            assert!(matches!(packet.msg, Message::Ping(_)));
+            let (pong, _) =
+                Message::Pong(Pong::new(Endpoint::new(from, 0), packet.hash)).encode(&server_key);
+            socket.send_to(&pong, from).await.unwrap();
+        });
+
+        let key = SecretKey::new(&mut rand::thread_rng());
+        let session = Discv4Session::connect(key, server, Duration::from_secs(2))
+            .await
+            .unwrap();
+        let started = Instant::now();
+        session.bond().await.unwrap();
+
+        // Verify that the bond completed without waiting for the ping until the timeout
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        handle.await.unwrap();
+    }
+}
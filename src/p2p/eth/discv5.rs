@@ -0,0 +1,69 @@
+use discv5::{
+    enr::{CombinedKey, NodeId},
+    ConfigBuilder, Discv5, Enr, ListenConfig,
+};
+use std::{collections::HashMap, net::Ipv4Addr, time::Duration};
+use tracing::{debug, instrument};
+
+use crate::p2p::error::{Discv5Error, P2PError};
+
+#[derive(Debug)]
+pub struct CrawlConfig {
+    pub bootnodes: Vec<Enr>,
+    pub timeout: u64,
+    pub lookups: usize,
+    pub max_nodes: usize,
+}
+
+/// Discover nodes over discovery v5 starting from the bootnodes.
+///
+/// Runs topic-less `FINDNODE` lookups towards random targets (each one performing the
+/// `WHOAREYOU` session handshake with every node it contacts) until `lookups` lookups were made
+/// or `max_nodes` records were found, and returns every discovered ENR.
+#[instrument(level = "trace", skip_all)]
+pub async fn crawl(config: CrawlConfig) -> Result<Vec<Enr>, P2PError> {
+    let key = CombinedKey::generate_secp256k1();
+    let local_enr = Enr::builder().build(&key).map_err(Discv5Error::from)?;
+    let listen_config = ListenConfig::Ipv4 {
+        ip: Ipv4Addr::UNSPECIFIED,
+        port: 0,
+    };
+    let discv5_config = ConfigBuilder::new(listen_config)
+        .request_timeout(Duration::from_millis(config.timeout))
+        .query_timeout(Duration::from_millis(config.timeout * 10))
+        .build();
+
+    let mut discv5: Discv5 =
+        Discv5::new(local_enr, key, discv5_config).map_err(Discv5Error::Config)?;
+    discv5.start().await.map_err(Discv5Error::Start)?;
+
+    for bootnode in config.bootnodes {
+        if let Err(err) = discv5.add_enr(bootnode) {
+            debug!("failed to add bootnode: {}", err);
+        }
+    }
+
+    let mut found: HashMap<NodeId, Enr> = HashMap::new();
+    for lookup in 0..config.lookups {
+        if found.len() >= config.max_nodes {
+            break;
+        }
+        match discv5.find_node(NodeId::random()).await {
+            Ok(enrs) => {
+                debug!("lookup {} returned {} records", lookup, enrs.len());
+                for enr in enrs {
+                    found.insert(enr.node_id(), enr);
+                }
+            }
+            Err(err) => debug!("lookup {} failed: {}", lookup, err),
+        }
+    }
+
+    // Nodes contacted during the lookups but not returned by them end up in the routing table
+    for enr in discv5.table_entries_enr() {
+        found.entry(enr.node_id()).or_insert(enr);
+    }
+    discv5.shutdown();
+
+    Ok(found.into_values().take(config.max_nodes).collect())
+}
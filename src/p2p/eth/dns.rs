@@ -0,0 +1,218 @@
+use discv5::Enr;
+use std::collections::{HashSet, VecDeque};
+use tracing::{debug, instrument, trace};
+
+use self::tree::{subdomain, RootEntry, TreeEntry};
+use crate::p2p::{error::DnsError, resolver::Resolver};
+
+pub use self::tree::LinkEntry;
+
+mod tree;
+
+/// [`MAX_TREE_ENTRIES`] bounds the number of entries fetched from a single tree.
+const MAX_TREE_ENTRIES: usize = 10_000;
+
+/// [`MAX_LINKED_TREES`] bounds the number of trees followed through link entries.
+const MAX_LINKED_TREES: usize = 16;
+
+/// Resolve an [EIP-1459](https://eips.ethereum.org/EIPS/eip-1459) node list.
+///
+/// The root of every tree is verified against the key of its link, and the records of the
+/// trees it links to are included as well.
+#[instrument(level = "trace", skip_all, fields(tree=%link))]
+pub async fn resolve<R: Resolver + ?Sized>(
+    resolver: &R,
+    link: &LinkEntry,
+) -> Result<Vec<Enr>, DnsError> {
+    let (mut enrs, mut links) = resolve_tree(resolver, link).await?;
+
+    let mut visited = HashSet::from([link.domain.clone()]);
+    while let Some(link) = links.pop_front() {
+        if visited.len() >= MAX_LINKED_TREES || !visited.insert(link.domain.clone()) {
+            continue;
+        }
+        match resolve_tree(resolver, &link).await {
+            Ok((linked_enrs, linked_links)) => {
+                enrs.extend(linked_enrs);
+                links.extend(linked_links);
+            }
+            Err(err) => debug!("failed to resolve linked tree {}: {}", link, err),
+        }
+    }
+
+    Ok(enrs)
+}
+
+/// Resolve the records and the links of a single tree
+async fn resolve_tree<R: Resolver + ?Sized>(
+    resolver: &R,
+    link: &LinkEntry,
+) -> Result<(Vec<Enr>, VecDeque<LinkEntry>), DnsError> {
+    let root: RootEntry = resolver
+        .lookup_txt(&link.domain)
+        .await?
+        .ok_or_else(|| DnsError::MissingEntry(link.domain.clone()))?
+        .parse()?;
+    root.verify(&link.pubkey)?;
+    trace!(?root, "verified tree root");
+
+    let mut enrs = Vec::new();
+    let mut links = VecDeque::new();
+    for entry in walk(resolver, &link.domain, &root.enr_root).await? {
+        match entry {
+            TreeEntry::Enr(enr) => enrs.push(*enr),
+            _ => debug!("ignoring link entry in the ENR subtree of {}", link.domain),
+        }
+    }
+    for entry in walk(resolver, &link.domain, &root.link_root).await? {
+        match entry {
+            TreeEntry::Link(link) => links.push_back(link),
+            _ => debug!("ignoring ENR entry in the link subtree of {}", link.domain),
+        }
+    }
+
+    Ok((enrs, links))
+}
+
+/// Fetch every leaf below `root`, checking that each entry matches the hash it was found at
+async fn walk<R: Resolver + ?Sized>(
+    resolver: &R,
+    domain: &str,
+    root: &str,
+) -> Result<Vec<TreeEntry>, DnsError> {
+    let mut leaves = Vec::new();
+    let mut visited = HashSet::new();
+    let mut pending = VecDeque::from([root.to_string()]);
+    while let Some(hash) = pending.pop_front() {
+        if !visited.insert(hash.to_ascii_uppercase()) {
+            continue;
+        }
+        if visited.len() > MAX_TREE_ENTRIES {
+            return Err(DnsError::TreeTooLarge(domain.to_string()));
+        }
+
+        let name = format!("{hash}.{domain}");
+        let txt = resolver
+            .lookup_txt(&name)
+            .await?
+            .ok_or_else(|| DnsError::MissingEntry(name.clone()))?;
+        if !subdomain(&txt).eq_ignore_ascii_case(&hash) {
+            return Err(DnsError::HashMismatch(name));
+        }
+
+        match txt.parse()? {
+            TreeEntry::Branch(children) => pending.extend(children),
+            leaf => leaves.push(leaf),
+        }
+    }
+
+    Ok(leaves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::p2p::resolver::StaticResolver;
+    use data_encoding::BASE64URL_NOPAD;
+    use discv5::enr::CombinedKey;
+    use reth_primitives::keccak256;
+    use secp256k1::{Message, SecretKey, SECP256K1};
+    use std::net::Ipv4Addr;
+
+    const DOMAIN: &str = "nodes.example.org";
+
+    /// Publish a tree with a single branch holding `enrs` and return its link
+    fn publish(zone: &mut StaticResolver, key: &SecretKey, enrs: &[Enr]) -> LinkEntry {
+        let leaves: Vec<String> = enrs.iter().map(|enr| enr.to_base64()).collect();
+        for leaf in &leaves {
+            zone.insert_txt(format!("{}.{}", subdomain(leaf), DOMAIN), leaf.clone());
+        }
+        let branch = format!(
+            "enrtree-branch:{}",
+            leaves
+                .iter()
+                .map(|leaf| subdomain(leaf))
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        zone.insert_txt(format!("{}.{}", subdomain(&branch), DOMAIN), branch.clone());
+        let links = "enrtree-branch:";
+        zone.insert_txt(format!("{}.{}", subdomain(links), DOMAIN), links);
+
+        let content = format!(
+            "enrtree-root:v1 e={} l={} seq=1",
+            subdomain(&branch),
+            subdomain(links)
+        );
+        let digest = Message::from_slice(keccak256(&content).as_slice()).unwrap();
+        let (recovery_id, signature) = SECP256K1
+            .sign_ecdsa_recoverable(&digest, key)
+            .serialize_compact();
+        let mut signature = signature.to_vec();
+        signature.push(recovery_id.to_i32() as u8);
+        zone.insert_txt(
+            DOMAIN,
+            format!("{} sig={}", content, BASE64URL_NOPAD.encode(&signature)),
+        );
+
+        LinkEntry {
+            domain: DOMAIN.to_string(),
+            pubkey: key.public_key(SECP256K1),
+        }
+    }
+
+    fn enr(port: u16) -> Enr {
+        Enr::builder()
+            .ip4(Ipv4Addr::new(10, 0, 0, 1))
+            .tcp4(port)
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_resolve_tree() {
+        let mut zone = StaticResolver::default();
+        let key = SecretKey::new(&mut rand::thread_rng());
+        let enrs = vec![enr(30303), enr(30304)];
+        let link = publish(&mut zone, &key, &enrs);
+
+        // Verify that the link survives a roundtrip through its text form
+        assert_eq!(link.to_string().parse::<LinkEntry>().unwrap(), link);
+
+        let mut resolved = resolve(&zone, &link).await.unwrap();
+        resolved.sort_by_key(|enr| enr.tcp4());
+        assert_eq!(resolved, enrs);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_tree_wrong_key() {
+        let mut zone = StaticResolver::default();
+        let key = SecretKey::new(&mut rand::thread_rng());
+        let mut link = publish(&mut zone, &key, &[enr(30303)]);
+        link.pubkey = SecretKey::new(&mut rand::thread_rng()).public_key(SECP256K1);
+
+        assert!(matches!(
+            resolve(&zone, &link).await,
+            Err(DnsError::InvalidSignature)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_tree_tampered_entry() {
+        let mut zone = StaticResolver::default();
+        let key = SecretKey::new(&mut rand::thread_rng());
+        let leaf = enr(30303);
+        let link = publish(&mut zone, &key, &[leaf.clone()]);
+
+        // Serve another record at the subdomain of the published one
+        zone.insert_txt(
+            format!("{}.{}", subdomain(&leaf.to_base64()), DOMAIN),
+            enr(30304).to_base64(),
+        );
+
+        assert!(matches!(
+            resolve(&zone, &link).await,
+            Err(DnsError::HashMismatch(_))
+        ));
+    }
+}
@@ -0,0 +1,147 @@
+use data_encoding::{BASE32_NOPAD, BASE64URL_NOPAD};
+use discv5::Enr;
+use reth_primitives::keccak256;
+use secp256k1::{ecdsa::Signature, Message, PublicKey, SECP256K1};
+use std::{fmt, str::FromStr};
+
+use crate::p2p::error::DnsError;
+
+const ROOT_PREFIX: &str = "enrtree-root:v1";
+const BRANCH_PREFIX: &str = "enrtree-branch:";
+const LINK_PREFIX: &str = "enrtree://";
+const ENR_PREFIX: &str = "enr:";
+
+/// A link to a node list tree: `enrtree://<base32 public key>@<domain>`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LinkEntry {
+    pub domain: String,
+    pub pubkey: PublicKey,
+}
+
+impl FromStr for LinkEntry {
+    type Err = DnsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, domain) = s
+            .strip_prefix(LINK_PREFIX)
+            .and_then(|link| link.split_once('@'))
+            .ok_or_else(|| DnsError::InvalidEntry(s.to_string()))?;
+        let key = BASE32_NOPAD
+            .decode(key.to_ascii_uppercase().as_bytes())
+            .map_err(|_| DnsError::InvalidEntry(s.to_string()))?;
+
+        Ok(Self {
+            domain: domain.to_string(),
+            pubkey: PublicKey::from_slice(&key)?,
+        })
+    }
+}
+
+impl fmt::Display for LinkEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}@{}",
+            LINK_PREFIX,
+            BASE32_NOPAD.encode(&self.pubkey.serialize()),
+            self.domain
+        )
+    }
+}
+
+/// The signed root of a tree:
+/// `enrtree-root:v1 e=<enr-root> l=<link-root> seq=<sequence-number> sig=<signature>`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct RootEntry {
+    pub(crate) enr_root: String,
+    pub(crate) link_root: String,
+    pub(crate) sequence: u64,
+    signature: Vec<u8>,
+}
+
+impl RootEntry {
+    /// The content covered by the signature, i.e. the record without the ` sig=` part
+    fn signed_content(&self) -> String {
+        format!(
+            "{} e={} l={} seq={}",
+            ROOT_PREFIX, self.enr_root, self.link_root, self.sequence
+        )
+    }
+
+    /// Verify that the root was signed by the key of the tree link
+    pub(crate) fn verify(&self, pubkey: &PublicKey) -> Result<(), DnsError> {
+        if self.signature.len() != 65 {
+            return Err(DnsError::InvalidSignature);
+        }
+        let digest = Message::from_slice(keccak256(self.signed_content()).as_slice())?;
+        let signature = Signature::from_compact(&self.signature[..64])?;
+        SECP256K1
+            .verify_ecdsa(&digest, &signature, pubkey)
+            .map_err(|_| DnsError::InvalidSignature)
+    }
+}
+
+impl FromStr for RootEntry {
+    type Err = DnsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || DnsError::InvalidEntry(s.to_string());
+        let mut fields = s
+            .strip_prefix(ROOT_PREFIX)
+            .ok_or_else(invalid)?
+            .split_whitespace();
+        let mut field = |name: &str| {
+            fields
+                .next()
+                .and_then(|field| field.strip_prefix(name))
+                .ok_or_else(invalid)
+        };
+
+        Ok(Self {
+            enr_root: field("e=")?.to_string(),
+            link_root: field("l=")?.to_string(),
+            sequence: field("seq=")?.parse().map_err(|_| invalid())?,
+            signature: BASE64URL_NOPAD
+                .decode(field("sig=")?.trim_end_matches('=').as_bytes())
+                .map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// The entries found below the root of a tree
+#[derive(Clone, Debug)]
+pub(crate) enum TreeEntry {
+    Branch(Vec<String>),
+    Enr(Box<Enr>),
+    Link(LinkEntry),
+}
+
+impl FromStr for TreeEntry {
+    type Err = DnsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(children) = s.strip_prefix(BRANCH_PREFIX) {
+            Ok(Self::Branch(
+                children
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|child| !child.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            ))
+        } else if s.starts_with(ENR_PREFIX) {
+            // Decoding the record also verifies its signature
+            let enr = s.parse().map_err(DnsError::InvalidEnr)?;
+            Ok(Self::Enr(Box::new(enr)))
+        } else if s.starts_with(LINK_PREFIX) {
+            Ok(Self::Link(s.parse()?))
+        } else {
+            Err(DnsError::InvalidEntry(s.to_string()))
+        }
+    }
+}
+
+/// The subdomain of an entry: the base32 encoding of the first 16 bytes of its keccak256 hash
+pub(crate) fn subdomain(entry: &str) -> String {
+    BASE32_NOPAD.encode(&keccak256(entry)[..16])
+}
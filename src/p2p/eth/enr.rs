@@ -0,0 +1,142 @@
+use alloy_rlp::{Decodable, Header};
+use data_encoding::BASE64URL_NOPAD;
+use discv5::{
+    enr::{CombinedPublicKey, EnrPublicKey},
+    Enr,
+};
+use reth_primitives::{hex, ForkId, NodeRecord, PeerId};
+use std::{fmt::Write, net::IpAddr};
+
+/// Convert an ENR into a handshake target using its TCP endpoint and secp256k1 key.
+///
+/// Returns `None` when the record does not advertise a TCP endpoint or uses another identity
+/// scheme than secp256k1 (RLPx requires a secp256k1 key).
+pub fn node_record(enr: &Enr) -> Option<NodeRecord> {
+    let (address, tcp_port) = match (enr.ip4(), enr.tcp4(), enr.ip6(), enr.tcp6()) {
+        (Some(ip), Some(port), _, _) => (IpAddr::V4(ip), port),
+        (_, _, Some(ip), Some(port)) => (IpAddr::V6(ip), port),
+        _ => return None,
+    };
+    let udp_port = enr.udp4().or(enr.udp6()).unwrap_or(tcp_port);
+    let id = match enr.public_key() {
+        key @ CombinedPublicKey::Secp256k1(_) => PeerId::from_slice(&key.encode_uncompressed()),
+        CombinedPublicKey::Ed25519(_) => return None,
+    };
+
+    Some(NodeRecord {
+        address,
+        tcp_port,
+        udp_port,
+        id,
+    })
+}
+
+/// Read the identity scheme (`id` entry) of a textual ENR, without verifying its signature.
+///
+/// Decoding an [`Enr`] fails on the signature of every scheme but `v4`, this tells them apart.
+pub fn identity_scheme(s: &str) -> Option<String> {
+    let record = BASE64URL_NOPAD
+        .decode(s.strip_prefix("enr:")?.as_bytes())
+        .ok()?;
+    let mut payload = Header::decode_bytes(&mut record.as_slice(), true).ok()?;
+    // Skip the signature and the sequence number, then look for the key among the pairs
+    skip_item(&mut payload)?;
+    skip_item(&mut payload)?;
+    while !payload.is_empty() {
+        let key = Header::decode_bytes(&mut payload, false).ok()?;
+        if key == b"id" {
+            let id = Header::decode_bytes(&mut payload, false).ok()?;
+            return String::from_utf8(id.to_vec()).ok();
+        }
+        skip_item(&mut payload)?;
+    }
+    None
+}
+
+/// Advance `buf` past its first RLP item, a string or a list
+fn skip_item(buf: &mut &[u8]) -> Option<()> {
+    let header = Header::decode(buf).ok()?;
+    *buf = buf.get(header.payload_length..)?;
+    Some(())
+}
+
+/// Decode the `eth` entry of an ENR, which advertises the fork id of an execution-layer node.
+///
+/// The entry is defined in [EIP-2124](https://eips.ethereum.org/EIPS/eip-2124) as `[[fork-hash,
+/// fork-next], ...]`, trailing elements are ignored.
+pub fn fork_id(enr: &Enr) -> Option<ForkId> {
+    let mut entry = enr.get_raw_rlp("eth")?;
+    let mut payload = Header::decode_bytes(&mut entry, true).ok()?;
+    ForkId::decode(&mut payload).ok()
+}
+
+/// Summarise the extra entries of an ENR reported alongside the handshake outcome
+pub fn summary(enr: &Enr) -> String {
+    let mut summary = format!("enr seq: {}", enr.seq());
+    if let Some(fork_id) = fork_id(enr) {
+        let _ = write!(
+            summary,
+            ", fork hash: 0x{}, fork next: {}",
+            hex::encode(fork_id.hash.0),
+            fork_id.next
+        );
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use discv5::enr::CombinedKey;
+    use reth_primitives::ForkHash;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_enr_conversion() {
+        let key = CombinedKey::generate_secp256k1();
+        let expected = ForkId {
+            hash: ForkHash([0xde, 0xad, 0xbe, 0xef]),
+            next: 0,
+        };
+        let enr = Enr::builder()
+            .ip4(Ipv4Addr::new(10, 0, 0, 1))
+            .tcp4(30303)
+            .udp4(30301)
+            .add_value_rlp("eth", alloy_rlp::encode(vec![expected]).into())
+            .build(&key)
+            .unwrap();
+
+        // Verify that the TCP endpoint and the key are used for the handshake target
+        let node = node_record(&enr).unwrap();
+        assert_eq!(node.address, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(node.tcp_port, 30303);
+        assert_eq!(node.udp_port, 30301);
+        assert_eq!(
+            node.id,
+            PeerId::from_slice(&enr.public_key().encode_uncompressed())
+        );
+
+        // Verify that the fork id is decoded from the `eth` entry
+        assert_eq!(fork_id(&enr), Some(expected));
+        assert_eq!(
+            summary(&enr),
+            format!(
+                "enr seq: {}, fork hash: 0xdeadbeef, fork next: 0",
+                enr.seq()
+            )
+        );
+    }
+
+    #[test]
+    fn test_enr_without_tcp_endpoint() {
+        let key = CombinedKey::generate_secp256k1();
+        let enr = Enr::builder()
+            .ip4(Ipv4Addr::new(10, 0, 0, 1))
+            .udp4(9000)
+            .build(&key)
+            .unwrap();
+
+        assert!(node_record(&enr).is_none());
+        assert!(fork_id(&enr).is_none());
+    }
+}
@@ -0,0 +1,140 @@
+use alloy_rlp::{Decodable, Encodable};
+use futures::{SinkExt, StreamExt};
+use reth_ecies::{stream::ECIESStream, util::pk2id};
+use reth_eth_wire::{DisconnectReason, P2PMessage};
+use reth_primitives::{
+    bytes::{Bytes, BytesMut},
+    NodeRecord,
+};
+use secp256k1::{SecretKey, SECP256K1};
+use std::{io, sync::Mutex};
+use tokio::{io::AsyncReadExt, net::TcpStream};
+
+use crate::p2p::{error::P2PError, eth::utils::create_hello_msg, mock::MockServer};
+
+/// [`MAX_FRAME_SIZE`] is the largest frame body the ECIES header can announce (24 bits), one
+/// byte short of the `MAX_PAYLOAD_SIZE` of a message: the size check of the first message is
+/// covered by the tests of the p2p stream instead.
+const MAX_FRAME_SIZE: usize = (1 << 24) - 1;
+
+/// How the mock node answers the connections
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Behavior {
+    /// Complete the ECIES and the Hello handshakes like a regular node
+    #[default]
+    Honest,
+    /// Complete the ECIES handshake, then disconnect instead of sending Hello
+    Disconnect(DisconnectReason),
+    /// Complete the ECIES handshake, then never send Hello
+    Stall,
+    /// Accept the connection and never answer the ECIES auth message
+    Silent,
+    /// Answer the ECIES handshake with another key than the one of its node record
+    WrongKey,
+    /// Complete the ECIES handshake, then send a frame of the maximum size instead of Hello, which
+    /// must be read and rejected rather than waited for
+    Oversized,
+}
+
+#[derive(Clone, Debug)]
+pub struct MockConfig {
+    pub behavior: Behavior,
+    /// Key of the node, its id is the one of [`MockPeer::record`]
+    pub key: SecretKey,
+}
+
+impl From<Behavior> for MockConfig {
+    fn from(behavior: Behavior) -> Self {
+        Self {
+            behavior,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        Self {
+            behavior: Behavior::default(),
+            key: SecretKey::new(&mut rand::thread_rng()),
+        }
+    }
+}
+
+/// A scripted RLPx node listening on a local port, stopped when dropped
+#[derive(Debug)]
+pub struct MockPeer {
+    record: NodeRecord,
+    server: MockServer<&'static str>,
+}
+
+impl MockPeer {
+    /// Listen on a random local port and answer every connection as configured
+    pub async fn spawn(config: MockConfig) -> io::Result<Self> {
+        let id = pk2id(&config.key.public_key(SECP256K1));
+        let server = MockServer::spawn(move |stream, _, received| {
+            let config = config.clone();
+            async move { serve(stream, &config, &received).await }
+        })
+        .await?;
+        Ok(Self {
+            record: NodeRecord::new(server.addr(), id),
+            server,
+        })
+    }
+
+    /// The record to perform the handshake with
+    pub fn record(&self) -> NodeRecord {
+        self.record
+    }
+
+    /// Names of the p2p messages received so far, over all the connections
+    pub fn received(&self) -> Vec<&'static str> {
+        self.server.received()
+    }
+}
+
+/// Answer a single connection
+async fn serve(
+    mut stream: TcpStream,
+    config: &MockConfig,
+    received: &Mutex<Vec<&'static str>>,
+) -> Result<(), P2PError> {
+    let key = match config.behavior {
+        Behavior::Silent => {
+            let mut buf = [0; 1024];
+            while stream.read(&mut buf).await? > 0 {}
+            return Ok(());
+        }
+        Behavior::WrongKey => SecretKey::new(&mut rand::thread_rng()),
+        _ => config.key,
+    };
+    let mut ecies = ECIESStream::incoming(stream, key).await?;
+
+    let first = match config.behavior {
+        Behavior::Honest => Some(P2PMessage::Hello(create_hello_msg(config.key))),
+        Behavior::Disconnect(reason) => Some(P2PMessage::Disconnect(reason)),
+        Behavior::Oversized => {
+            ecies.send(Bytes::from(vec![0; MAX_FRAME_SIZE])).await?;
+            None
+        }
+        _ => None,
+    };
+    if let Some(msg) = first {
+        let mut buf = BytesMut::new();
+        msg.encode(&mut buf);
+        ecies.send(buf.freeze()).await?;
+    }
+
+    while let Some(frame) = ecies.next().await {
+        if let Ok(msg) = P2PMessage::decode(&mut &frame?[..]) {
+            received.lock().unwrap().push(match msg {
+                P2PMessage::Hello(_) => "hello",
+                P2PMessage::Disconnect(_) => "disconnect",
+                P2PMessage::Ping => "ping",
+                P2PMessage::Pong => "pong",
+            });
+        }
+    }
+    Ok(())
+}
@@ -228,4 +228,46 @@ mod tests {
         // Make sure the server sends the disconnect message before ending the test
         handle.await.unwrap();
     }
+
+    #[tokio::test]
+    async fn test_handshake_message_too_big() {
+        // Frames above the default limit of the codec are needed to exceed the payload size
+        let codec = || {
+            LengthDelimitedCodec::builder()
+                .max_frame_length(2 * MAX_PAYLOAD_SIZE)
+                .new_codec()
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let local_addr = listener.local_addr().unwrap();
+
+        let handle = tokio::spawn(async move {
+            // Read the hello of the client and answer with an oversized message
+            let (incoming, _) = listener.accept().await.unwrap();
+            let mut stream = codec().framed(incoming);
+            stream.next().await.unwrap().unwrap();
+            let _ = stream
+                .send(Bytes::from(vec![0; MAX_PAYLOAD_SIZE + 1]))
+                .await;
+        });
+
+        let outgoing = TcpStream::connect(local_addr).await.unwrap();
+        let sink = codec().framed(outgoing);
+
+        let client_hello = create_hello_msg(SecretKey::new(&mut rand::thread_rng()));
+
+        // Confirm that the message is rejected before being decoded
+        let p2p_stream = P2PStream::new(sink);
+        match p2p_stream.handshake(client_hello, 5000).await {
+            Err(P2PStreamError::MessageTooBig {
+                message_size,
+                max_size,
+            }) => {
+                assert_eq!(message_size, MAX_PAYLOAD_SIZE + 1);
+                assert_eq!(max_size, MAX_PAYLOAD_SIZE);
+            }
+            res => panic!("expected the message to be too big, got {res:?}"),
+        }
+
+        handle.await.unwrap();
+    }
 }
@@ -0,0 +1,232 @@
+use discv5::Enr;
+use reth_primitives::{NodeRecord, PeerId};
+use std::{net::Ipv4Addr, str::FromStr};
+use tracing::error;
+
+use crate::p2p::eth::{
+    dns::{self, LinkEntry, Resolver},
+    enr::{identity_scheme, node_record, summary},
+};
+
+/// A handshake target given on the command line
+#[derive(Clone, Debug)]
+pub enum Target {
+    /// A single node: `enode://<node_id>@<ip_address>:<port>`
+    Enode(NodeRecord),
+    /// A single node behind a hostname: `enode://<node_id>@<hostname>:<port>`
+    EnodeHost { id: PeerId, host: String, port: u16 },
+    /// A single node record ([EIP-778](https://eips.ethereum.org/EIPS/eip-778)): `enr:-<base64>`
+    Enr(Box<Enr>),
+    /// A node list published over DNS: `enrtree://<public_key>@<domain>`
+    EnrTree(LinkEntry),
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("enrtree://") {
+            s.parse()
+                .map(Target::EnrTree)
+                .map_err(|err| err.to_string())
+        } else if s.starts_with("enr:") {
+            // Only `v4` signatures can be verified, check the scheme before decoding the record
+            if let Some(id) = identity_scheme(s).filter(|id| id != "v4") {
+                return Err(format!("unsupported ENR identity scheme {id}"));
+            }
+            // Decoding the record verifies its signature
+            let enr: Enr = s.parse()?;
+            if node_record(&enr).is_none() {
+                return Err("ENR without a TCP endpoint or a secp256k1 key".to_string());
+            }
+            Ok(Target::Enr(Box::new(enr)))
+        } else {
+            s.parse()
+                .map(Target::Enode)
+                .or_else(|err| parse_enode_host(s).ok_or_else(|| err.to_string()))
+        }
+    }
+}
+
+/// Parse an enode URL whose host is a name rather than an IP address
+fn parse_enode_host(s: &str) -> Option<Target> {
+    let (id, authority) = s.strip_prefix("enode://")?.split_once('@')?;
+    // Drop the `?discport=` query, the discovery port is not used for the handshake
+    let authority = authority.split('?').next()?;
+    let (host, port) = authority.rsplit_once(':')?;
+    if host.is_empty() || host.contains(':') {
+        return None;
+    }
+    Some(Target::EnodeHost {
+        id: id.parse().ok()?,
+        host: host.to_string(),
+        port: port.parse().ok()?,
+    })
+}
+
+/// A node to perform the handshake with, and the record it was found in, if any
+#[derive(Clone, Debug)]
+pub struct Peer {
+    pub node: NodeRecord,
+    pub enr: Option<Box<Enr>>,
+    /// Hostname resolved when connecting, in place of the address of `node`
+    pub host: Option<String>,
+}
+
+impl Peer {
+    /// Create a peer from a record with a TCP endpoint and a secp256k1 key
+    pub fn from_enr(enr: Enr) -> Option<Self> {
+        Some(Self {
+            node: node_record(&enr)?,
+            enr: Some(Box::new(enr)),
+            host: None,
+        })
+    }
+
+    /// Extra information from the record reported alongside the handshake outcome
+    pub fn extras(&self) -> Option<String> {
+        match (&self.enr, &self.host) {
+            (Some(enr), _) => Some(summary(enr)),
+            (None, Some(host)) => Some(format!("host: {}", host)),
+            (None, None) => None,
+        }
+    }
+}
+
+impl From<NodeRecord> for Peer {
+    fn from(node: NodeRecord) -> Self {
+        Self {
+            node,
+            enr: None,
+            host: None,
+        }
+    }
+}
+
+/// Expand the targets into the peers to handshake, resolving node lists with `resolver`
+pub async fn resolve_targets<R: Resolver + ?Sized>(
+    targets: Vec<Target>,
+    resolver: &R,
+) -> Vec<Peer> {
+    let mut peers = Vec::new();
+    for target in targets {
+        match target {
+            Target::Enode(node) => peers.push(node.into()),
+            Target::EnodeHost { id, host, port } => peers.push(Peer {
+                node: NodeRecord::new((Ipv4Addr::UNSPECIFIED, port).into(), id),
+                enr: None,
+                host: Some(host),
+            }),
+            Target::Enr(enr) => peers.extend(Peer::from_enr(*enr)),
+            Target::EnrTree(link) => match dns::resolve(resolver, &link).await {
+                Ok(enrs) => peers.extend(enrs.into_iter().filter_map(Peer::from_enr)),
+                Err(err) => error!("[failed] [{}] error: {}", link, err),
+            },
+        }
+    }
+    peers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_rlp::{Encodable, Header};
+    use data_encoding::BASE64URL_NOPAD;
+    use discv5::enr::CombinedKey;
+    use reth_primitives::keccak256;
+    use secp256k1::{Message, SecretKey, SECP256K1};
+
+    const NODE_ID: &str = "7723cea4576dd5b4b92dad365da58604329866e84ad0689d86892566c087fce6f87836467dc9c9ab59fc03eeae3eede68e01b4984c4bba60ec20fc25063a3ecc";
+
+    #[test]
+    fn test_parse_enode_host() {
+        let target: Target = format!("enode://{NODE_ID}@node.example.org:30303?discport=30301")
+            .parse()
+            .unwrap();
+        let Target::EnodeHost { id, host, port } = target else {
+            panic!("expected a hostname target, got {target:?}");
+        };
+        assert_eq!(id, NODE_ID.parse::<PeerId>().unwrap());
+        assert_eq!(host, "node.example.org");
+        assert_eq!(port, 30303);
+
+        // Verify that literal addresses are still parsed as node records
+        let target: Target = format!("enode://{NODE_ID}@3.239.83.130:30303")
+            .parse()
+            .unwrap();
+        assert!(matches!(target, Target::Enode(_)));
+
+        assert!(format!("enode://{NODE_ID}@node.example.org")
+            .parse::<Target>()
+            .is_err());
+    }
+
+    /// Sign a record with a TCP endpoint as the `v4` scheme does, whatever its `id` entry
+    fn signed_record(id: &str, key: &SecretKey) -> String {
+        let list = |payload: &[u8]| {
+            let mut list = Vec::new();
+            Header {
+                list: true,
+                payload_length: payload.len(),
+            }
+            .encode(&mut list);
+            list.extend_from_slice(payload);
+            list
+        };
+        let mut content = Vec::new();
+        1u64.encode(&mut content);
+        b"id".as_slice().encode(&mut content);
+        id.as_bytes().encode(&mut content);
+        b"ip".as_slice().encode(&mut content);
+        [10u8, 0, 0, 1].as_slice().encode(&mut content);
+        b"secp256k1".as_slice().encode(&mut content);
+        key.public_key(SECP256K1)
+            .serialize()
+            .as_slice()
+            .encode(&mut content);
+        b"tcp".as_slice().encode(&mut content);
+        30303u16.encode(&mut content);
+
+        let digest = Message::from_slice(keccak256(list(&content)).as_slice()).unwrap();
+        let signature = SECP256K1.sign_ecdsa(&digest, key).serialize_compact();
+        let mut record = Vec::new();
+        signature.as_slice().encode(&mut record);
+        record.extend_from_slice(&content);
+        format!("enr:{}", BASE64URL_NOPAD.encode(&list(&record)))
+    }
+
+    #[test]
+    fn test_parse_enr() {
+        let key = CombinedKey::generate_secp256k1();
+        let enr = Enr::builder()
+            .ip4(Ipv4Addr::new(10, 0, 0, 1))
+            .tcp4(30303)
+            .build(&key)
+            .unwrap();
+        let target: Target = enr.to_base64().parse().unwrap();
+        let Target::Enr(parsed) = target else {
+            panic!("expected a node record target, got {target:?}");
+        };
+        assert_eq!(*parsed, enr);
+
+        // Verify that another identity scheme is rejected, although the record is well signed
+        let secret = SecretKey::new(&mut rand::thread_rng());
+        let target: Target = signed_record("v4", &secret).parse().unwrap();
+        assert!(matches!(target, Target::Enr(_)));
+        assert_eq!(
+            signed_record("v5", &secret).parse::<Target>().unwrap_err(),
+            "unsupported ENR identity scheme v5"
+        );
+
+        // Verify that a record without a TCP endpoint cannot be handshaked
+        let enr = Enr::builder()
+            .ip4(Ipv4Addr::new(10, 0, 0, 1))
+            .udp4(30301)
+            .build(&key)
+            .unwrap();
+        assert_eq!(
+            enr.to_base64().parse::<Target>().unwrap_err(),
+            "ENR without a TCP endpoint or a secp256k1 key"
+        );
+    }
+}
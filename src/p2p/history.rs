@@ -0,0 +1,283 @@
+use rusqlite::{named_params, Connection};
+use std::{
+    fmt,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::p2p::{error::P2PError, HandshakeReport};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS handshakes (
+    id INTEGER PRIMARY KEY,
+    target TEXT NOT NULL,
+    protocol TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    success INTEGER NOT NULL,
+    error_kind TEXT,
+    error TEXT,
+    latency_ms REAL NOT NULL,
+    remote TEXT,
+    local TEXT,
+    peer_info TEXT
+);
+CREATE INDEX IF NOT EXISTS handshakes_target_timestamp ON handshakes (target, timestamp);
+CREATE TABLE IF NOT EXISTS handshake_phases (
+    handshake_id INTEGER NOT NULL REFERENCES handshakes (id),
+    phase TEXT NOT NULL,
+    duration_ms REAL NOT NULL,
+    PRIMARY KEY (handshake_id, phase)
+);
+";
+
+/// Handshake attempts recorded in a SQLite database
+#[derive(Debug)]
+pub struct History {
+    conn: Connection,
+}
+
+/// Filters and grouping of the history query
+#[derive(Clone, Debug, Default)]
+pub struct HistoryQuery {
+    pub target: Option<String>,
+    /// Only the attempts made from this time on
+    pub since: Option<SystemTime>,
+    /// Length of the periods the attempts of a peer are grouped by, the whole history when unset
+    pub bucket: Option<Duration>,
+}
+
+/// Uptime and latency of a peer over a period
+#[derive(Clone, Debug, PartialEq)]
+pub struct PeerHistory {
+    pub target: String,
+    pub protocol: String,
+    /// Start of the period, when grouping by periods
+    pub period: Option<SystemTime>,
+    pub attempts: u64,
+    pub successes: u64,
+    pub average_latency: Option<Duration>,
+    pub max_latency: Option<Duration>,
+    pub last_success: Option<SystemTime>,
+}
+
+impl PeerHistory {
+    /// Share of the successful attempts, in percent
+    pub fn uptime(&self) -> f64 {
+        self.successes as f64 * 100.0 / self.attempts.max(1) as f64
+    }
+}
+
+impl fmt::Display for PeerHistory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] [{}] ", self.protocol, self.target)?;
+        if let Some(period) = self.period {
+            write!(f, "period: {} ", humantime::format_rfc3339_seconds(period))?;
+        }
+        write!(
+            f,
+            "uptime: {:.1}% ({}/{})",
+            self.uptime(),
+            self.successes,
+            self.attempts
+        )?;
+        if let (Some(average), Some(max)) = (self.average_latency, self.max_latency) {
+            write!(f, " latency average: {:?} max: {:?}", average, max)?;
+        }
+        match self.last_success {
+            Some(time) => write!(
+                f,
+                " last success: {}",
+                humantime::format_rfc3339_seconds(time)
+            ),
+            None => write!(f, " last success: never"),
+        }
+    }
+}
+
+impl History {
+    /// Open the database, creating it and its schema if needed
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    /// Record the attempts of a handshake round, with the duration of their phases
+    pub fn record(&mut self, reports: &[HandshakeReport]) -> rusqlite::Result<()> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut insert = tx.prepare_cached(
+                "INSERT INTO handshakes (target, protocol, timestamp, success, error_kind, error,
+                    latency_ms, remote, local, peer_info)
+                VALUES (:target, :protocol, :timestamp, :success, :error_kind, :error,
+                    :latency_ms, :remote, :local, :peer_info)",
+            )?;
+            let mut insert_phase = tx.prepare_cached(
+                "INSERT OR REPLACE INTO handshake_phases (handshake_id, phase, duration_ms)
+                VALUES (:handshake_id, :phase, :duration_ms)",
+            )?;
+            for report in reports {
+                let (outcome, err) = match &report.result {
+                    Ok(outcome) => (Some(outcome), None),
+                    Err(err) => (None, Some(err)),
+                };
+                insert.execute(named_params! {
+                    ":target": report.target,
+                    ":protocol": report.protocol,
+                    ":timestamp": to_millis(report.timestamp),
+                    ":success": outcome.is_some(),
+                    ":error_kind": err.map(|err| err.kind()),
+                    ":error": err.map(P2PError::message),
+                    ":latency_ms": report.latency.as_secs_f64() * 1000.0,
+                    ":remote": outcome.map(|outcome| outcome.addr.to_string()),
+                    ":local": outcome.map(|outcome| outcome.local.to_string()),
+                    ":peer_info": outcome.and_then(|outcome| outcome.extras.clone()),
+                })?;
+                let handshake_id = tx.last_insert_rowid();
+                for (phase, duration) in &report.phases {
+                    insert_phase.execute(named_params! {
+                        ":handshake_id": handshake_id,
+                        ":phase": phase,
+                        ":duration_ms": duration.as_secs_f64() * 1000.0,
+                    })?;
+                }
+            }
+        }
+        tx.commit()
+    }
+
+    /// Uptime and latency of every recorded peer, by period when grouping by periods
+    pub fn peers(&self, query: &HistoryQuery) -> rusqlite::Result<Vec<PeerHistory>> {
+        let bucket = query
+            .bucket
+            .map_or(i64::MAX, |bucket| (bucket.as_millis() as i64).max(1));
+        let mut select = self.conn.prepare(
+            "SELECT target, protocol, (timestamp / :bucket) * :bucket AS period, COUNT(*),
+                SUM(success), AVG(CASE WHEN success THEN latency_ms END),
+                MAX(CASE WHEN success THEN latency_ms END),
+                MAX(CASE WHEN success THEN timestamp END)
+            FROM handshakes
+            WHERE timestamp >= :since AND (:target IS NULL OR target = :target)
+            GROUP BY target, protocol, period
+            ORDER BY target, protocol, period",
+        )?;
+        let rows = select.query_map(
+            named_params! {
+                ":bucket": bucket,
+                ":since": query.since.map_or(0, to_millis),
+                ":target": query.target,
+            },
+            |row| {
+                Ok(PeerHistory {
+                    target: row.get(0)?,
+                    protocol: row.get(1)?,
+                    period: query.bucket.map(|_| from_millis(row.get(2)?)),
+                    attempts: row.get(3)?,
+                    successes: row.get(4)?,
+                    average_latency: row
+                        .get::<_, Option<f64>>(5)?
+                        .map(|ms| Duration::from_secs_f64(ms / 1000.0)),
+                    max_latency: row
+                        .get::<_, Option<f64>>(6)?
+                        .map(|ms| Duration::from_secs_f64(ms / 1000.0)),
+                    last_success: row.get::<_, Option<i64>>(7)?.map(from_millis),
+                })
+            },
+        )?;
+        rows.collect()
+    }
+}
+
+fn to_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as i64)
+}
+
+fn from_millis(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::p2p::{connect::Destination, error::P2PHandshake, HandshakeOutcome};
+    use std::io;
+
+    fn report(target: &str, timestamp: SystemTime, latency_ms: Option<u64>) -> HandshakeReport {
+        let result = match latency_ms {
+            Some(_) => Ok(HandshakeOutcome {
+                addr: Destination::Addr("10.0.0.1:8333".parse().unwrap()),
+                local: "10.0.0.2:50000".parse().unwrap(),
+                extras: None,
+            }),
+            None => Err(P2PError::P2PHandshakeError(P2PHandshake::new(
+                P2PError::IOError(io::ErrorKind::ConnectionRefused.into()),
+                target.to_string(),
+            ))),
+        };
+        HandshakeReport {
+            protocol: "btc",
+            target: target.to_string(),
+            timestamp,
+            latency: Duration::from_millis(latency_ms.unwrap_or(1000)),
+            phases: vec![(
+                "connect",
+                Duration::from_millis(latency_ms.unwrap_or(1000) / 2),
+            )],
+            result,
+        }
+    }
+
+    #[test]
+    fn test_uptime_and_latency() {
+        let mut history = History::open(Path::new(":memory:")).unwrap();
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let hour = Duration::from_secs(3600);
+        history
+            .record(&[
+                report("a", start, Some(100)),
+                report("b", start, None),
+                report("a", start + hour, Some(300)),
+                report("a", start + hour * 2, None),
+            ])
+            .unwrap();
+
+        // Verify the summary of each peer over the whole history
+        let peers = history.peers(&HistoryQuery::default()).unwrap();
+        assert_eq!(peers.len(), 2);
+        assert_eq!((peers[0].attempts, peers[0].successes), (3, 2));
+        assert_eq!(peers[0].average_latency, Some(Duration::from_millis(200)));
+        assert_eq!(peers[0].last_success, Some(start + hour));
+        assert_eq!((peers[1].attempts, peers[1].successes), (1, 0));
+        assert_eq!(peers[1].last_success, None);
+
+        // Verify that the attempts are filtered and grouped by period
+        let periods = history
+            .peers(&HistoryQuery {
+                target: Some("a".to_string()),
+                since: Some(start + hour),
+                bucket: Some(hour),
+            })
+            .unwrap();
+        assert_eq!(periods.len(), 2);
+        assert_eq!(periods[0].period, Some(start + hour));
+        assert_eq!(periods[0].uptime(), 100.0);
+        assert_eq!(periods[1].uptime(), 0.0);
+
+        // Verify that the phases are stored with their attempt
+        let phases: Vec<(String, String, f64)> = history
+            .conn
+            .prepare(
+                "SELECT target, phase, duration_ms FROM handshake_phases
+                JOIN handshakes ON handshakes.id = handshake_id ORDER BY handshake_id",
+            )
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(phases.len(), 4);
+        assert_eq!(phases[0], ("a".to_string(), "connect".to_string(), 50.0));
+        assert_eq!(phases[1], ("b".to_string(), "connect".to_string(), 500.0));
+    }
+}
@@ -0,0 +1,243 @@
+use futures::{future::poll_fn, Future};
+use libp2p::{
+    core::{
+        muxing::{StreamMuxer, StreamMuxerExt},
+        upgrade::{self, Version},
+    },
+    identity::Keypair,
+    multiaddr::Protocol,
+    noise, yamux, Multiaddr, PeerId,
+};
+use measure_time::info_time;
+use multistream_select::Negotiated;
+use std::{
+    net::SocketAddr,
+    pin::pin,
+    task::Poll,
+    time::{Duration, Instant},
+};
+use tokio::net::TcpStream;
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
+use tracing::{info, instrument, trace};
+
+use crate::{
+    p2p::{
+        connect::{Connector, Destination, Endpoints},
+        error::{Libp2pError, P2PError},
+    },
+    telemetry::HandshakeMetrics,
+};
+
+pub mod identify;
+#[cfg(feature = "test-utils")]
+pub mod mock;
+
+#[derive(Debug)]
+pub struct Config {
+    /// `/ip4|ip6|dns/<host>/tcp/<port>`, with the expected `/p2p/<peer id>` of the peer
+    pub address: Multiaddr,
+    pub timeout: u64,
+    pub connector: Connector,
+}
+
+/// Perform a libp2p handshake with a peer
+#[instrument(level = "trace", skip_all, fields(peer=&*format!("{}", config.address)))]
+pub async fn handshake(config: Config) -> Result<Endpoints, P2PError> {
+    info_time!("[{}] Perform a P2P handshake", config.address);
+
+    let metrics = HandshakeMetrics::new("libp2p");
+    let mut session =
+        Session::connect(&config.address, config.timeout, &config.connector, &metrics).await?;
+
+    // Ask the peer to identify itself on a new stream
+    let started = Instant::now();
+    let info = tokio::time::timeout(
+        Duration::from_millis(config.timeout),
+        session.run(identify::request),
+    )
+    .await??;
+    metrics.phase("identify", started.elapsed());
+
+    info!(
+        "[{}] peer id: {} agent version: {} protocol version: {} protocols: {}",
+        session.destination,
+        session.peer_id,
+        info.agent_version,
+        info.protocol_version,
+        info.protocols.join(" ")
+    );
+    Ok(session.endpoints)
+}
+
+/// The yamux connection to a peer, over noise
+type Muxer = yamux::Muxer<Negotiated<noise::Output<Negotiated<Compat<TcpStream>>>>>;
+
+/// An authenticated and multiplexed connection to a peer
+pub struct Session {
+    pub destination: Destination,
+    /// The peer id authenticated by noise
+    pub peer_id: PeerId,
+    pub endpoints: Endpoints,
+    muxer: Muxer,
+}
+
+impl Session {
+    /// Dial a multiaddr, then negotiate noise and yamux with the peer and verify its peer id
+    pub async fn connect(
+        address: &Multiaddr,
+        timeout: u64,
+        connector: &Connector,
+        metrics: &HandshakeMetrics,
+    ) -> Result<Self, P2PError> {
+        let (destination, expected) = parse_address(address)?;
+        let timeout = Duration::from_millis(timeout);
+
+        let started = Instant::now();
+        let connection = tokio::time::timeout(timeout, connector.connect(&destination)).await??;
+        metrics.phase("connect", started.elapsed());
+
+        // Negotiate noise over multistream-select and authenticate the peer
+        let started = Instant::now();
+        let key = Keypair::generate_ed25519();
+        let noise = noise::Config::new(&key).map_err(|err| Libp2pError::Noise(err.to_string()))?;
+        let (peer_id, stream) = tokio::time::timeout(
+            timeout,
+            upgrade::apply_outbound(connection.stream.compat(), noise, Version::V1),
+        )
+        .await?
+        .map_err(|err| Libp2pError::Noise(err.to_string()))?;
+        if let Some(expected) = expected {
+            if peer_id != expected {
+                return Err(Libp2pError::PeerIdMismatch {
+                    expected,
+                    actual: peer_id,
+                }
+                .into());
+            }
+        }
+        metrics.phase("noise", started.elapsed());
+
+        let started = Instant::now();
+        let muxer = tokio::time::timeout(
+            timeout,
+            upgrade::apply_outbound(stream, yamux::Config::default(), Version::V1),
+        )
+        .await?
+        .map_err(|err| Libp2pError::Yamux(err.to_string()))?;
+        metrics.phase("yamux", started.elapsed());
+
+        Ok(Self {
+            destination,
+            peer_id,
+            endpoints: connection.endpoints,
+            muxer,
+        })
+    }
+
+    /// Open a new stream and run `protocol` on it, driving the connection meanwhile
+    pub async fn run<F, Fut, T, E>(&mut self, protocol: F) -> Result<T, E>
+    where
+        F: FnOnce(yamux::Stream) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: From<Libp2pError>,
+    {
+        let stream = poll_fn(|cx| self.muxer.poll_outbound_unpin(cx))
+            .await
+            .map_err(|err| Libp2pError::Yamux(err.to_string()))?;
+        drive(&mut self.muxer, protocol(stream)).await
+    }
+}
+
+/// The destination to dial and the expected peer id of a multiaddr
+pub fn parse_address(address: &Multiaddr) -> Result<(Destination, Option<PeerId>), Libp2pError> {
+    let invalid = || Libp2pError::InvalidAddress(address.to_string());
+    let mut protocols = address.iter();
+    let destination = match (protocols.next(), protocols.next()) {
+        (Some(Protocol::Ip4(ip)), Some(Protocol::Tcp(port))) => {
+            Destination::Addr(SocketAddr::new(ip.into(), port))
+        }
+        (Some(Protocol::Ip6(ip)), Some(Protocol::Tcp(port))) => {
+            Destination::Addr(SocketAddr::new(ip.into(), port))
+        }
+        (
+            Some(Protocol::Dns(host) | Protocol::Dns4(host) | Protocol::Dns6(host)),
+            Some(Protocol::Tcp(port)),
+        ) => Destination::Host {
+            host: host.to_string(),
+            port,
+        },
+        _ => return Err(invalid()),
+    };
+    match (protocols.next(), protocols.next()) {
+        (None, _) => Ok((destination, None)),
+        (Some(Protocol::P2p(peer_id)), None) => Ok((destination, Some(peer_id))),
+        _ => Err(invalid()),
+    }
+}
+
+/// Drive the connection until `fut` completes, dropping the streams opened by the peer
+pub(crate) async fn drive<M, F, T, E>(muxer: &mut M, fut: F) -> Result<T, E>
+where
+    M: StreamMuxer + Unpin,
+    M::Error: std::fmt::Display,
+    F: Future<Output = Result<T, E>>,
+    E: From<Libp2pError>,
+{
+    let mut fut = pin!(fut);
+    poll_fn(|cx| {
+        if let Poll::Ready(res) = fut.as_mut().poll(cx) {
+            return Poll::Ready(res);
+        }
+        loop {
+            match muxer.poll_inbound_unpin(cx) {
+                Poll::Ready(Ok(_)) => trace!("dropping a stream opened by the peer"),
+                Poll::Ready(Err(err)) => {
+                    return Poll::Ready(Err(Libp2pError::Yamux(err.to_string()).into()))
+                }
+                Poll::Pending => break,
+            }
+        }
+        loop {
+            match muxer.poll_unpin(cx) {
+                Poll::Ready(Ok(_)) => (),
+                Poll::Ready(Err(err)) => {
+                    return Poll::Ready(Err(Libp2pError::Yamux(err.to_string()).into()))
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_address() {
+        let peer_id = Keypair::generate_ed25519().public().to_peer_id();
+        let address: Multiaddr = format!("/ip4/10.0.0.1/tcp/9000/p2p/{}", peer_id)
+            .parse()
+            .unwrap();
+        assert_eq!(
+            parse_address(&address).unwrap(),
+            (
+                Destination::Addr("10.0.0.1:9000".parse().unwrap()),
+                Some(peer_id)
+            )
+        );
+        assert_eq!(
+            parse_address(&"/dns/boot.example.org/tcp/4001".parse().unwrap()).unwrap(),
+            (
+                Destination::Host {
+                    host: "boot.example.org".to_string(),
+                    port: 4001
+                },
+                None
+            )
+        );
+        assert!(parse_address(&"/ip4/10.0.0.1/udp/9000/quic-v1".parse().unwrap()).is_err());
+        assert!(parse_address(&"/ip4/10.0.0.1/tcp/9000/ws".parse().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite};
+use libp2p::Multiaddr;
+use multistream_select::{dialer_select_proto, Version};
+use tracing::trace;
+
+use crate::p2p::error::Libp2pError;
+
+/// [`PROTOCOL`] is the protocol id of the identify protocol.
+pub const PROTOCOL: &str = "/ipfs/id/1.0.0";
+
+/// [`MAX_MESSAGE_SIZE`] is the largest identify message accepted.
+const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// The identify message a peer answers with
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Info {
+    /// Protobuf encoded public key of the peer
+    pub public_key: Vec<u8>,
+    pub listen_addrs: Vec<Multiaddr>,
+    pub protocols: Vec<String>,
+    /// The address of the client as seen by the peer
+    pub observed_addr: Option<Multiaddr>,
+    pub protocol_version: String,
+    pub agent_version: String,
+}
+
+impl Info {
+    /// Decode the protobuf encoded message
+    pub fn decode(mut data: &[u8]) -> Result<Self, Libp2pError> {
+        let mut info = Info::default();
+        while !data.is_empty() {
+            let key = read_varint(&mut data)?;
+            match (key >> 3, key & 0x7) {
+                (_, 0) => {
+                    read_varint(&mut data)?;
+                }
+                (_, 1) => {
+                    split(&mut data, 8)?;
+                }
+                (_, 5) => {
+                    split(&mut data, 4)?;
+                }
+                (field, 2) => {
+                    let len = read_varint(&mut data)? as usize;
+                    let value = split(&mut data, len)?;
+                    match field {
+                        1 => info.public_key = value.to_vec(),
+                        // Addresses of unknown protocols are skipped
+                        2 => info
+                            .listen_addrs
+                            .extend(Multiaddr::try_from(value.to_vec()).ok()),
+                        3 => info.protocols.push(string(value)?),
+                        4 => info.observed_addr = Multiaddr::try_from(value.to_vec()).ok(),
+                        5 => info.protocol_version = string(value)?,
+                        6 => info.agent_version = string(value)?,
+                        _ => (),
+                    }
+                }
+                _ => return Err(Libp2pError::InvalidIdentify("unsupported wire type")),
+            }
+        }
+        Ok(info)
+    }
+
+    /// Encode the message to protobuf
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_field(&mut buf, 1, &self.public_key);
+        for addr in &self.listen_addrs {
+            write_field(&mut buf, 2, &addr.to_vec());
+        }
+        for protocol in &self.protocols {
+            write_field(&mut buf, 3, protocol.as_bytes());
+        }
+        if let Some(addr) = &self.observed_addr {
+            write_field(&mut buf, 4, &addr.to_vec());
+        }
+        write_field(&mut buf, 5, self.protocol_version.as_bytes());
+        write_field(&mut buf, 6, self.agent_version.as_bytes());
+        buf
+    }
+
+    /// The message prefixed with its length, as sent on the stream
+    pub fn to_frame(&self) -> Vec<u8> {
+        let message = self.encode();
+        let mut frame = Vec::with_capacity(message.len() + 4);
+        write_varint(&mut frame, message.len() as u64);
+        frame.extend_from_slice(&message);
+        frame
+    }
+}
+
+/// Negotiate the identify protocol on a new stream and read the answer of the peer
+pub async fn request<S>(stream: S) -> Result<Info, Libp2pError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (_, stream) = dialer_select_proto(stream, [PROTOCOL], Version::V1).await?;
+    trace!("identify negotiated, reading the answer ...");
+
+    // The peer closes the stream once its message is sent
+    let mut frame = Vec::new();
+    stream
+        .take(MAX_MESSAGE_SIZE as u64 + 10)
+        .read_to_end(&mut frame)
+        .await?;
+    let mut data = frame.as_slice();
+    let len = read_varint(&mut data)? as usize;
+    if len > MAX_MESSAGE_SIZE {
+        return Err(Libp2pError::InvalidIdentify("message too large"));
+    }
+    Info::decode(split(&mut data, len)?)
+}
+
+pub(crate) fn read_varint(data: &mut &[u8]) -> Result<u64, Libp2pError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = data
+            .split_first()
+            .ok_or(Libp2pError::InvalidIdentify("truncated varint"))?;
+        *data = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(Libp2pError::InvalidIdentify("varint overflow"))
+}
+
+pub(crate) fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn write_field(buf: &mut Vec<u8>, field: u64, value: &[u8]) {
+    write_varint(buf, (field << 3) | 2);
+    write_varint(buf, value.len() as u64);
+    buf.extend_from_slice(value);
+}
+
+fn split<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], Libp2pError> {
+    if data.len() < len {
+        return Err(Libp2pError::InvalidIdentify("truncated field"));
+    }
+    let (value, rest) = data.split_at(len);
+    *data = rest;
+    Ok(value)
+}
+
+fn string(value: &[u8]) -> Result<String, Libp2pError> {
+    String::from_utf8(value.to_vec()).map_err(|_| Libp2pError::InvalidIdentify("invalid string"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_info_roundtrip() {
+        let info = Info {
+            public_key: vec![0x08, 0x01, 0x12, 0x20],
+            listen_addrs: vec!["/ip4/127.0.0.1/tcp/4001".parse().unwrap()],
+            protocols: vec![PROTOCOL.to_string(), "/ipfs/ping/1.0.0".to_string()],
+            observed_addr: Some("/ip4/192.0.2.10/tcp/51234".parse().unwrap()),
+            protocol_version: "ipfs/0.1.0".to_string(),
+            agent_version: "kubo/0.24.0/".to_string(),
+        };
+
+        // Verify that the message decodes back, skipping the unknown fields
+        let mut message = info.encode();
+        message.extend_from_slice(&[8 << 3, 0x96, 0x01]);
+        assert_eq!(Info::decode(&message).unwrap(), info);
+
+        let frame = info.to_frame();
+        let mut data = frame.as_slice();
+        assert_eq!(read_varint(&mut data).unwrap() as usize, data.len());
+
+        // Verify that a truncated message is rejected
+        assert!(Info::decode(&[(6 << 3) | 2, 0x05, b'k']).is_err());
+    }
+}
@@ -0,0 +1,149 @@
+use futures::{future::poll_fn, AsyncWriteExt};
+use libp2p::{
+    core::{muxing::StreamMuxerExt, upgrade},
+    identity::Keypair,
+    noise, yamux, Multiaddr, PeerId,
+};
+use multistream_select::listener_select_proto;
+use std::{io, net::SocketAddr, sync::Mutex};
+use tokio::net::TcpStream;
+use tokio_util::compat::TokioAsyncReadCompatExt;
+
+use crate::p2p::{
+    error::Libp2pError,
+    libp2p::{
+        drive,
+        identify::{self, Info},
+    },
+    mock::MockServer,
+};
+
+/// How the mock peer answers the connections
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Behavior {
+    /// Complete the handshake and answer the identify requests
+    #[default]
+    Honest,
+    /// Accept the connection and never answer
+    Stall,
+    /// Refuse to negotiate the identify protocol
+    NoIdentify,
+}
+
+#[derive(Clone, Debug)]
+pub struct MockConfig {
+    pub behavior: Behavior,
+    pub key: Keypair,
+    /// Sent in reply to the identify requests
+    pub info: Info,
+}
+
+impl From<Behavior> for MockConfig {
+    fn from(behavior: Behavior) -> Self {
+        Self {
+            behavior,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        Self {
+            behavior: Behavior::default(),
+            key: Keypair::generate_ed25519(),
+            info: Info {
+                protocols: vec![identify::PROTOCOL.to_string()],
+                protocol_version: "ipfs/0.1.0".to_string(),
+                agent_version: "mock/0.1.0".to_string(),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// A scripted libp2p peer listening on a local port, stopped when dropped
+#[derive(Debug)]
+pub struct MockPeer {
+    peer_id: PeerId,
+    server: MockServer<String>,
+}
+
+impl MockPeer {
+    /// Listen on a random local port and answer every connection as configured
+    pub async fn spawn(config: MockConfig) -> io::Result<Self> {
+        let peer_id = config.key.public().to_peer_id();
+        let server = MockServer::spawn(move |stream, _, received| {
+            let config = config.clone();
+            async move { serve(stream, &config, &received).await }
+        })
+        .await?;
+        Ok(Self { peer_id, server })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.server.addr()
+    }
+
+    /// The multiaddr of the peer, with its peer id
+    pub fn address(&self) -> Multiaddr {
+        multiaddr(self.addr(), self.peer_id)
+    }
+
+    pub fn peer_id(&self) -> PeerId {
+        self.peer_id
+    }
+
+    /// Protocols negotiated so far, over all the connections
+    pub fn received(&self) -> Vec<String> {
+        self.server.received()
+    }
+}
+
+/// The `/ip4/<ip>/tcp/<port>/p2p/<peer id>` multiaddr of a local mock
+pub(crate) fn multiaddr(addr: SocketAddr, peer_id: PeerId) -> Multiaddr {
+    format!("/ip4/{}/tcp/{}/p2p/{}", addr.ip(), addr.port(), peer_id)
+        .parse()
+        .unwrap()
+}
+
+/// Answer a single connection
+async fn serve(
+    stream: TcpStream,
+    config: &MockConfig,
+    received: &Mutex<Vec<String>>,
+) -> Result<(), Libp2pError> {
+    if config.behavior == Behavior::Stall {
+        return futures::future::pending().await;
+    }
+
+    let noise =
+        noise::Config::new(&config.key).map_err(|err| Libp2pError::Noise(err.to_string()))?;
+    let (_, stream) = upgrade::apply_inbound(stream.compat(), noise)
+        .await
+        .map_err(|err| Libp2pError::Noise(err.to_string()))?;
+    received.lock().unwrap().push("/noise".to_string());
+    let mut muxer = upgrade::apply_inbound(stream, yamux::Config::default())
+        .await
+        .map_err(|err| Libp2pError::Yamux(err.to_string()))?;
+    received.lock().unwrap().push("/yamux/1.0.0".to_string());
+
+    let stream = poll_fn(|cx| muxer.poll_inbound_unpin(cx))
+        .await
+        .map_err(|err| Libp2pError::Yamux(err.to_string()))?;
+    let protocols = match config.behavior {
+        Behavior::NoIdentify => vec!["/ipfs/ping/1.0.0"],
+        _ => vec![identify::PROTOCOL],
+    };
+    let answer = async {
+        let (protocol, mut stream) = listener_select_proto(stream, protocols).await?;
+        received.lock().unwrap().push(protocol.to_string());
+        stream.write_all(&config.info.to_frame()).await?;
+        stream.close().await?;
+        Ok::<_, Libp2pError>(())
+    };
+    drive(&mut muxer, answer).await?;
+
+    // Keep the connection up until the client closes it
+    drive(&mut muxer, futures::future::pending()).await
+}
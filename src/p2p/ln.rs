@@ -0,0 +1,124 @@
+use bitcoin::{blockdata::constants::genesis_block, hashes::Hash, Network};
+use data_encoding::HEXLOWER;
+use measure_time::info_time;
+use secp256k1::SecretKey;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, info, instrument};
+
+use crate::{
+    p2p::{
+        connect::{Connector, Endpoints},
+        error::{LnError, P2PError},
+        ln::{message::Init, target::Target},
+    },
+    telemetry::HandshakeMetrics,
+};
+
+pub mod message;
+#[cfg(feature = "test-utils")]
+pub mod mock;
+pub mod noise;
+pub mod target;
+
+/// Feature bits announced in our init: the ones the nodes require from their peers nowadays
+const LOCAL_FEATURES: &[usize] = &[1, 8, 12, 14];
+
+#[derive(Debug)]
+pub struct Config {
+    pub node: Target,
+    pub timeout: u64,
+    pub connector: Connector,
+    /// Chain announced in our init
+    pub network: Network,
+}
+
+/// Perform the noise handshake with a lightning node and exchange the init messages
+#[instrument(level = "trace", skip_all, fields(peer=&*format!("{}", config.node)))]
+pub async fn handshake(config: Config) -> Result<Endpoints, P2PError> {
+    info_time!(
+        "[{}] Perform a lightning handshake",
+        config.node.destination
+    );
+
+    let metrics = HandshakeMetrics::new("ln");
+    let timeout = Duration::from_millis(config.timeout);
+
+    let started = Instant::now();
+    let mut connection =
+        tokio::time::timeout(timeout, config.connector.connect(&config.node.destination)).await??;
+    metrics.phase("connect", started.elapsed());
+
+    // Acts one to three, authenticating the node by its static key
+    let started = Instant::now();
+    let key = SecretKey::new(&mut rand::thread_rng());
+    let mut transport = tokio::time::timeout(
+        timeout,
+        noise::initiate(&mut connection.stream, key, &config.node.node_id),
+    )
+    .await??;
+    metrics.phase("noise", started.elapsed());
+
+    let started = Instant::now();
+    let ours = Init::new(LOCAL_FEATURES, vec![chain_hash(config.network)]);
+    let init = tokio::time::timeout(timeout, async {
+        transport
+            .write_message(&mut connection.stream, &ours.encode())
+            .await?;
+        Init::decode(&transport.read_message(&mut connection.stream).await?)
+    })
+    .await??;
+    metrics.phase("init", started.elapsed());
+
+    // Nothing else to exchange, close our side of the connection
+    if let Err(err) = connection.stream.shutdown().await {
+        debug!("[{}] shutdown failed: {}", config.node.destination, err);
+    }
+
+    info!(
+        "[{}] node id: {} features: {} chains: {}",
+        config.node.destination,
+        config.node.node_id,
+        init.feature_names().join(" "),
+        init.networks
+            .iter()
+            .map(chain_name)
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+    Ok(connection.endpoints)
+}
+
+/// The hash of the genesis block identifying a chain in the lightning messages
+pub fn chain_hash(network: Network) -> [u8; 32] {
+    genesis_block(network).block_hash().to_byte_array()
+}
+
+/// The name of the bitcoin network of a chain hash, its hex encoding otherwise
+fn chain_name(hash: &[u8; 32]) -> String {
+    [
+        Network::Bitcoin,
+        Network::Testnet,
+        Network::Signet,
+        Network::Regtest,
+    ]
+    .into_iter()
+    .find(|network| chain_hash(*network) == *hash)
+    .map(|network| network.to_string())
+    .unwrap_or_else(|| HEXLOWER.encode(hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain_hash() {
+        assert_eq!(
+            HEXLOWER.encode(&chain_hash(Network::Bitcoin)),
+            "6fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000"
+        );
+        assert_eq!(chain_name(&chain_hash(Network::Signet)), "signet");
+        assert_eq!(chain_name(&[0x42; 32]), "42".repeat(32));
+    }
+}
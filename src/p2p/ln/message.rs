@@ -0,0 +1,258 @@
+use crate::p2p::error::LnError;
+
+/// [`INIT`] is the type of the first message sent on a connection.
+pub const INIT: u16 = 16;
+
+/// TLV of the chains the node is interested in
+const NETWORKS_TLV: u64 = 1;
+
+/// Names of the features, by their even (compulsory) bit
+const FEATURES: &[(usize, &str)] = &[
+    (0, "option_data_loss_protect"),
+    (4, "option_upfront_shutdown_script"),
+    (6, "gossip_queries"),
+    (8, "var_onion_optin"),
+    (10, "gossip_queries_ex"),
+    (12, "option_static_remotekey"),
+    (14, "payment_secret"),
+    (16, "basic_mpp"),
+    (18, "option_support_large_channel"),
+    (22, "option_anchors_zero_fee_htlc_tx"),
+    (24, "option_route_blinding"),
+    (26, "option_shutdown_anysegwit"),
+    (28, "option_dual_fund"),
+    (38, "option_onion_messages"),
+    (44, "option_channel_type"),
+    (46, "option_scid_alias"),
+    (48, "option_payment_metadata"),
+    (50, "option_zeroconf"),
+];
+
+/// The `init` message of BOLT #1
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Init {
+    /// Legacy feature bits, merged with `features` by the nodes
+    pub global_features: Vec<u8>,
+    /// Feature bitfield, the least significant bit of the last byte is bit 0
+    pub features: Vec<u8>,
+    /// Chain hashes of the networks the node is interested in
+    pub networks: Vec<[u8; 32]>,
+}
+
+impl Init {
+    /// An init message setting the given feature bits
+    pub fn new(bits: &[usize], networks: Vec<[u8; 32]>) -> Self {
+        let len = bits.iter().map(|bit| bit / 8 + 1).max().unwrap_or(0);
+        let mut features = vec![0; len];
+        for bit in bits {
+            features[len - 1 - bit / 8] |= 1 << (bit % 8);
+        }
+        Self {
+            global_features: Vec::new(),
+            features,
+            networks,
+        }
+    }
+
+    /// The feature bits set in either bitfield, in ascending order
+    pub fn feature_bits(&self) -> Vec<usize> {
+        let len = self.features.len().max(self.global_features.len());
+        (0..len * 8)
+            .filter(|bit| is_set(&self.features, *bit) || is_set(&self.global_features, *bit))
+            .collect()
+    }
+
+    /// The feature bits with their name when known, e.g. `payment_secret(14)`
+    pub fn feature_names(&self) -> Vec<String> {
+        self.feature_bits()
+            .into_iter()
+            .map(
+                |bit| match FEATURES.iter().find(|(even, _)| *even == bit & !1) {
+                    Some((_, name)) => format!("{}({})", name, bit),
+                    None => bit.to_string(),
+                },
+            )
+            .collect()
+    }
+
+    /// Encode the message with its type
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = INIT.to_be_bytes().to_vec();
+        buf.extend_from_slice(&(self.global_features.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&self.global_features);
+        buf.extend_from_slice(&(self.features.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&self.features);
+        if !self.networks.is_empty() {
+            write_bigsize(&mut buf, NETWORKS_TLV);
+            write_bigsize(&mut buf, 32 * self.networks.len() as u64);
+            for network in &self.networks {
+                buf.extend_from_slice(network);
+            }
+        }
+        buf
+    }
+
+    /// Decode a message, which must be an init message
+    pub fn decode(mut data: &[u8]) -> Result<Self, LnError> {
+        let message_type = read_u16(&mut data)?;
+        if message_type != INIT {
+            return Err(LnError::UnexpectedMessage(message_type));
+        }
+        let len = read_u16(&mut data)? as usize;
+        let global_features = split(&mut data, len)?.to_vec();
+        let len = read_u16(&mut data)? as usize;
+        let features = split(&mut data, len)?.to_vec();
+
+        let mut networks = Vec::new();
+        let mut last_type = None;
+        while !data.is_empty() {
+            let tlv_type = read_bigsize(&mut data)?;
+            if last_type.is_some_and(|last| tlv_type <= last) {
+                return Err(LnError::InvalidMessage("TLV types not increasing"));
+            }
+            last_type = Some(tlv_type);
+            let len = read_bigsize(&mut data)?;
+            let value = split(&mut data, usize::try_from(len).unwrap_or(usize::MAX))?;
+            match tlv_type {
+                NETWORKS_TLV if value.len() % 32 == 0 => {
+                    networks = value
+                        .chunks_exact(32)
+                        .map(|hash| hash.try_into().unwrap())
+                        .collect();
+                }
+                NETWORKS_TLV => return Err(LnError::InvalidMessage("invalid networks TLV")),
+                // It is ok to ignore the unknown odd TLVs, not the even ones
+                tlv_type if tlv_type % 2 == 0 => {
+                    return Err(LnError::InvalidMessage("unknown even TLV"))
+                }
+                _ => (),
+            }
+        }
+        Ok(Self {
+            global_features,
+            features,
+            networks,
+        })
+    }
+}
+
+fn is_set(bitfield: &[u8], bit: usize) -> bool {
+    bit / 8 < bitfield.len() && bitfield[bitfield.len() - 1 - bit / 8] & (1 << (bit % 8)) != 0
+}
+
+fn read_u16(data: &mut &[u8]) -> Result<u16, LnError> {
+    let bytes = split(data, 2)?;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+/// Read a BigSize integer, which must be minimally encoded
+fn read_bigsize(data: &mut &[u8]) -> Result<u64, LnError> {
+    let (value, min) = match split(data, 1)?[0] {
+        0xfd => (u64::from(read_u16(data)?), 0xfd),
+        0xfe => {
+            let bytes = split(data, 4)?;
+            (
+                u64::from(u32::from_be_bytes(bytes.try_into().unwrap())),
+                0x1_0000,
+            )
+        }
+        0xff => {
+            let bytes = split(data, 8)?;
+            (u64::from_be_bytes(bytes.try_into().unwrap()), 0x1_0000_0000)
+        }
+        byte => return Ok(u64::from(byte)),
+    };
+    if value < min {
+        return Err(LnError::InvalidMessage("non minimal BigSize"));
+    }
+    Ok(value)
+}
+
+fn write_bigsize(buf: &mut Vec<u8>, value: u64) {
+    match value {
+        0..=0xfc => buf.push(value as u8),
+        0xfd..=0xffff => {
+            buf.push(0xfd);
+            buf.extend_from_slice(&(value as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            buf.push(0xfe);
+            buf.extend_from_slice(&(value as u32).to_be_bytes());
+        }
+        _ => {
+            buf.push(0xff);
+            buf.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+}
+
+fn split<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], LnError> {
+    if data.len() < len {
+        return Err(LnError::InvalidMessage("truncated message"));
+    }
+    let (value, rest) = data.split_at(len);
+    *data = rest;
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_roundtrip() {
+        let init = Init::new(&[1, 8, 12, 14, 45], vec![[0x6f; 32], [0x43; 32]]);
+        assert_eq!(init.features, vec![0x20, 0, 0, 0, 0x51, 0x02]);
+        assert_eq!(init.feature_bits(), vec![1, 8, 12, 14, 45]);
+        assert_eq!(
+            init.feature_names(),
+            vec![
+                "option_data_loss_protect(1)",
+                "var_onion_optin(8)",
+                "option_static_remotekey(12)",
+                "payment_secret(14)",
+                "option_channel_type(45)"
+            ]
+        );
+        assert_eq!(Init::decode(&init.encode()).unwrap(), init);
+
+        // Verify that the legacy bits are merged and the unknown odd TLVs skipped
+        let mut message = Init {
+            global_features: vec![0x01, 0x00],
+            ..init.clone()
+        }
+        .encode();
+        message.extend_from_slice(&[0x03, 0x02, 0x7f, 0x00]);
+        let decoded = Init::decode(&message).unwrap();
+        assert_eq!(decoded.networks, init.networks);
+        assert_eq!(decoded.feature_bits(), vec![1, 8, 12, 14, 45]);
+
+        // Verify that the other messages and the unknown even TLVs are rejected
+        assert!(matches!(
+            Init::decode(&[0x00, 0x12, 0x00, 0x00]),
+            Err(LnError::UnexpectedMessage(18))
+        ));
+        let mut message = init.encode();
+        message.extend_from_slice(&[0x04, 0x00]);
+        assert!(Init::decode(&message).is_err());
+    }
+
+    #[test]
+    fn test_bigsize() {
+        for value in [
+            0,
+            0xfc,
+            0xfd,
+            0xffff,
+            0x1_0000,
+            0xffff_ffff,
+            0x1_0000_0000,
+            u64::MAX,
+        ] {
+            let mut buf = Vec::new();
+            write_bigsize(&mut buf, value);
+            assert_eq!(read_bigsize(&mut buf.as_slice()).unwrap(), value);
+        }
+        assert!(read_bigsize(&mut [0xfd, 0x00, 0xfc].as_slice()).is_err());
+    }
+}
@@ -0,0 +1,115 @@
+use bitcoin::Network;
+use secp256k1::{PublicKey, SecretKey, SECP256K1};
+use std::{io, net::SocketAddr, sync::Mutex};
+use tokio::net::TcpStream;
+
+use crate::p2p::{
+    error::LnError,
+    ln::{chain_hash, message::Init, noise, target::Target},
+    mock::MockServer,
+};
+
+/// [`PING`] is the type of the message sent instead of init by [`Behavior::SkipInit`].
+const PING: u16 = 18;
+
+/// How the mock node answers the connections
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Behavior {
+    /// Complete the handshake and answer the init message
+    #[default]
+    Honest,
+    /// Accept the connection and never answer
+    Stall,
+    /// Complete the handshake, then send a ping instead of init
+    SkipInit,
+}
+
+#[derive(Clone, Debug)]
+pub struct MockConfig {
+    pub behavior: Behavior,
+    pub key: SecretKey,
+    /// Sent in reply to the init of the client
+    pub init: Init,
+}
+
+impl From<Behavior> for MockConfig {
+    fn from(behavior: Behavior) -> Self {
+        Self {
+            behavior,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        Self {
+            behavior: Behavior::default(),
+            key: SecretKey::new(&mut rand::thread_rng()),
+            init: Init::new(&[1, 9, 13, 15], vec![chain_hash(Network::Bitcoin)]),
+        }
+    }
+}
+
+/// A scripted lightning node listening on a local port, stopped when dropped
+#[derive(Debug)]
+pub struct MockNode {
+    node_id: PublicKey,
+    server: MockServer<Init>,
+}
+
+impl MockNode {
+    /// Listen on a random local port and answer every connection as configured
+    pub async fn spawn(config: MockConfig) -> io::Result<Self> {
+        let node_id = config.key.public_key(SECP256K1);
+        let server = MockServer::spawn(move |stream, _, received| {
+            let config = config.clone();
+            async move { serve(stream, &config, &received).await }
+        })
+        .await?;
+        Ok(Self { node_id, server })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.server.addr()
+    }
+
+    /// The `<node_id>@<ip_address>:<port>` target of the node
+    pub fn target(&self) -> Target {
+        Target {
+            node_id: self.node_id,
+            destination: self.addr().into(),
+        }
+    }
+
+    /// Init messages received so far, over all the connections
+    pub fn received(&self) -> Vec<Init> {
+        self.server.received()
+    }
+}
+
+/// Answer a single connection
+async fn serve(
+    mut stream: TcpStream,
+    config: &MockConfig,
+    received: &Mutex<Vec<Init>>,
+) -> Result<(), LnError> {
+    if config.behavior == Behavior::Stall {
+        return futures::future::pending().await;
+    }
+
+    let (_, mut transport) = noise::respond(&mut stream, &config.key).await?;
+    let init = Init::decode(&transport.read_message(&mut stream).await?)?;
+    received.lock().unwrap().push(init);
+
+    let reply = match config.behavior {
+        Behavior::SkipInit => [PING.to_be_bytes(), 0u16.to_be_bytes(), 0u16.to_be_bytes()].concat(),
+        _ => config.init.encode(),
+    };
+    transport.write_message(&mut stream, &reply).await?;
+
+    // Keep the connection up until the client closes it
+    loop {
+        transport.read_message(&mut stream).await?;
+    }
+}
@@ -0,0 +1,443 @@
+use chacha20poly1305::{
+    aead::{AeadInPlace, KeyInit},
+    ChaCha20Poly1305, Key, Nonce, Tag,
+};
+use hkdf::Hkdf;
+use secp256k1::{ecdh::SharedSecret, PublicKey, SecretKey, SECP256K1};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::p2p::error::LnError;
+
+pub const ACT_ONE_SIZE: usize = 50;
+pub const ACT_TWO_SIZE: usize = 50;
+pub const ACT_THREE_SIZE: usize = 66;
+
+/// [`MAX_MESSAGE_SIZE`] is the largest message the length prefix can carry.
+pub const MAX_MESSAGE_SIZE: usize = u16::MAX as usize;
+
+const PROTOCOL_NAME: &[u8] = b"Noise_XK_secp256k1_ChaChaPoly_SHA256";
+const PROLOGUE: &[u8] = b"lightning";
+const TAG_SIZE: usize = 16;
+/// Number of encryptions after which a transport key is rotated
+const KEY_ROTATION: u64 = 1000;
+
+/// Perform the handshake as the initiator with the node of static key `remote`
+pub async fn initiate<S>(
+    stream: &mut S,
+    local: SecretKey,
+    remote: &PublicKey,
+) -> Result<Transport, LnError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (initiator, act_one) =
+        Initiator::new(local, SecretKey::new(&mut rand::thread_rng()), remote);
+    stream.write_all(&act_one).await?;
+    let mut act_two = [0; ACT_TWO_SIZE];
+    stream.read_exact(&mut act_two).await?;
+    let (act_three, transport) = initiator.finish(&act_two)?;
+    stream.write_all(&act_three).await?;
+    Ok(transport)
+}
+
+/// Perform the handshake as the responder, returning the static key of the initiator
+pub async fn respond<S>(
+    stream: &mut S,
+    local: &SecretKey,
+) -> Result<(PublicKey, Transport), LnError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut act_one = [0; ACT_ONE_SIZE];
+    stream.read_exact(&mut act_one).await?;
+    let (responder, act_two) =
+        Responder::new(local, SecretKey::new(&mut rand::thread_rng()), &act_one)?;
+    stream.write_all(&act_two).await?;
+    let mut act_three = [0; ACT_THREE_SIZE];
+    stream.read_exact(&mut act_three).await?;
+    responder.finish(&act_three)
+}
+
+/// The initiator side of the handshake, waiting for act two
+pub struct Initiator {
+    state: SymmetricState,
+    local: SecretKey,
+    ephemeral: SecretKey,
+}
+
+impl Initiator {
+    /// Start the handshake with the node of static key `remote`, returning act one
+    pub fn new(
+        local: SecretKey,
+        ephemeral: SecretKey,
+        remote: &PublicKey,
+    ) -> (Self, [u8; ACT_ONE_SIZE]) {
+        let mut state = SymmetricState::new(remote);
+        let e = ephemeral.public_key(SECP256K1).serialize();
+        state.mix_hash(&e);
+        let temp_k1 = state.mix_key(&ecdh(remote, &ephemeral));
+        let c = state.encrypt_and_hash(&temp_k1, 0, &[]);
+
+        let mut act_one = [0; ACT_ONE_SIZE];
+        act_one[1..34].copy_from_slice(&e);
+        act_one[34..].copy_from_slice(&c);
+        let initiator = Self {
+            state,
+            local,
+            ephemeral,
+        };
+        (initiator, act_one)
+    }
+
+    /// Process act two, returning act three and the transport of the session
+    pub fn finish(
+        mut self,
+        act_two: &[u8; ACT_TWO_SIZE],
+    ) -> Result<([u8; ACT_THREE_SIZE], Transport), LnError> {
+        if act_two[0] != 0 {
+            return Err(LnError::InvalidVersion(act_two[0], 2));
+        }
+        let re = PublicKey::from_slice(&act_two[1..34])?;
+        self.state.mix_hash(&act_two[1..34]);
+        let temp_k2 = self.state.mix_key(&ecdh(&re, &self.ephemeral));
+        self.state.decrypt_and_hash(&temp_k2, 0, &act_two[34..])?;
+
+        // Reveal our static key, encrypted
+        let s = self.local.public_key(SECP256K1).serialize();
+        let c = self.state.encrypt_and_hash(&temp_k2, 1, &s);
+        let temp_k3 = self.state.mix_key(&ecdh(&re, &self.local));
+        let t = encrypt(&temp_k3, 0, &self.state.h, &[]);
+        let (sk, rk) = hkdf(&self.state.ck, &[]);
+
+        let mut act_three = [0; ACT_THREE_SIZE];
+        act_three[1..50].copy_from_slice(&c);
+        act_three[50..].copy_from_slice(&t);
+        Ok((act_three, Transport::new(sk, rk, self.state.ck)))
+    }
+}
+
+/// The responder side of the handshake, waiting for act three
+pub struct Responder {
+    state: SymmetricState,
+    ephemeral: SecretKey,
+    temp_k2: [u8; 32],
+}
+
+impl Responder {
+    /// Process the act one sent to the node of static key `local`, returning act two
+    pub fn new(
+        local: &SecretKey,
+        ephemeral: SecretKey,
+        act_one: &[u8; ACT_ONE_SIZE],
+    ) -> Result<(Self, [u8; ACT_TWO_SIZE]), LnError> {
+        if act_one[0] != 0 {
+            return Err(LnError::InvalidVersion(act_one[0], 1));
+        }
+        let mut state = SymmetricState::new(&local.public_key(SECP256K1));
+        let re = PublicKey::from_slice(&act_one[1..34])?;
+        state.mix_hash(&act_one[1..34]);
+        let temp_k1 = state.mix_key(&ecdh(&re, local));
+        state.decrypt_and_hash(&temp_k1, 0, &act_one[34..])?;
+
+        let e = ephemeral.public_key(SECP256K1).serialize();
+        state.mix_hash(&e);
+        let temp_k2 = state.mix_key(&ecdh(&re, &ephemeral));
+        let c = state.encrypt_and_hash(&temp_k2, 0, &[]);
+
+        let mut act_two = [0; ACT_TWO_SIZE];
+        act_two[1..34].copy_from_slice(&e);
+        act_two[34..].copy_from_slice(&c);
+        let responder = Self {
+            state,
+            ephemeral,
+            temp_k2,
+        };
+        Ok((responder, act_two))
+    }
+
+    /// Process act three, returning the static key of the initiator and the transport
+    pub fn finish(
+        mut self,
+        act_three: &[u8; ACT_THREE_SIZE],
+    ) -> Result<(PublicKey, Transport), LnError> {
+        if act_three[0] != 0 {
+            return Err(LnError::InvalidVersion(act_three[0], 3));
+        }
+        let s = self
+            .state
+            .decrypt_and_hash(&self.temp_k2, 1, &act_three[1..50])?;
+        let rs = PublicKey::from_slice(&s)?;
+        let temp_k3 = self.state.mix_key(&ecdh(&rs, &self.ephemeral));
+        decrypt(&temp_k3, 0, &self.state.h, &act_three[50..])?;
+        let (rk, sk) = hkdf(&self.state.ck, &[]);
+        Ok((rs, Transport::new(sk, rk, self.state.ck)))
+    }
+}
+
+/// The chaining key and the handshake hash
+struct SymmetricState {
+    ck: [u8; 32],
+    h: [u8; 32],
+}
+
+impl SymmetricState {
+    fn new(responder: &PublicKey) -> Self {
+        let h: [u8; 32] = Sha256::digest(PROTOCOL_NAME).into();
+        let mut state = Self { ck: h, h };
+        state.mix_hash(PROLOGUE);
+        state.mix_hash(&responder.serialize());
+        state
+    }
+
+    fn mix_hash(&mut self, data: &[u8]) {
+        self.h = Sha256::new()
+            .chain_update(self.h)
+            .chain_update(data)
+            .finalize()
+            .into();
+    }
+
+    /// Update the chaining key and return the temporary key of the act
+    fn mix_key(&mut self, ikm: &[u8]) -> [u8; 32] {
+        let (ck, key) = hkdf(&self.ck, ikm);
+        self.ck = ck;
+        key
+    }
+
+    fn encrypt_and_hash(&mut self, key: &[u8; 32], nonce: u64, plaintext: &[u8]) -> Vec<u8> {
+        let c = encrypt(key, nonce, &self.h, plaintext);
+        self.mix_hash(&c);
+        c
+    }
+
+    fn decrypt_and_hash(
+        &mut self,
+        key: &[u8; 32],
+        nonce: u64,
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, LnError> {
+        let plaintext = decrypt(key, nonce, &self.h, ciphertext)?;
+        self.mix_hash(ciphertext);
+        Ok(plaintext)
+    }
+}
+
+/// The encryption of the messages of a session in one direction
+struct CipherState {
+    key: [u8; 32],
+    nonce: u64,
+    ck: [u8; 32],
+}
+
+impl CipherState {
+    fn encrypt(&mut self, plaintext: &[u8]) -> Vec<u8> {
+        let c = encrypt(&self.key, self.nonce, &[], plaintext);
+        self.next();
+        c
+    }
+
+    fn decrypt(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>, LnError> {
+        let plaintext = decrypt(&self.key, self.nonce, &[], ciphertext)?;
+        self.next();
+        Ok(plaintext)
+    }
+
+    fn next(&mut self) {
+        self.nonce += 1;
+        if self.nonce == KEY_ROTATION {
+            (self.ck, self.key) = hkdf(&self.ck, &self.key);
+            self.nonce = 0;
+        }
+    }
+}
+
+/// The encrypted and authenticated messages of an established session
+pub struct Transport {
+    sending: CipherState,
+    receiving: CipherState,
+}
+
+impl Transport {
+    fn new(sk: [u8; 32], rk: [u8; 32], ck: [u8; 32]) -> Self {
+        Self {
+            sending: CipherState {
+                key: sk,
+                nonce: 0,
+                ck,
+            },
+            receiving: CipherState {
+                key: rk,
+                nonce: 0,
+                ck,
+            },
+        }
+    }
+
+    /// Encrypt a message, prefixed with its encrypted length
+    pub fn encrypt(&mut self, message: &[u8]) -> Result<Vec<u8>, LnError> {
+        if message.len() > MAX_MESSAGE_SIZE {
+            return Err(LnError::MessageTooLarge(message.len()));
+        }
+        let mut packet = self.sending.encrypt(&(message.len() as u16).to_be_bytes());
+        packet.extend(self.sending.encrypt(message));
+        Ok(packet)
+    }
+
+    pub async fn write_message<W>(&mut self, stream: &mut W, message: &[u8]) -> Result<(), LnError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        stream.write_all(&self.encrypt(message)?).await?;
+        Ok(())
+    }
+
+    pub async fn read_message<R>(&mut self, stream: &mut R) -> Result<Vec<u8>, LnError>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let mut header = [0; 2 + TAG_SIZE];
+        stream.read_exact(&mut header).await?;
+        let len = self.receiving.decrypt(&header)?;
+        let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+
+        let mut body = vec![0; len + TAG_SIZE];
+        stream.read_exact(&mut body).await?;
+        self.receiving.decrypt(&body)
+    }
+}
+
+/// The SHA256 of the compressed shared point
+fn ecdh(point: &PublicKey, scalar: &SecretKey) -> [u8; 32] {
+    SharedSecret::new(point, scalar).secret_bytes()
+}
+
+fn hkdf(salt: &[u8; 32], ikm: &[u8]) -> ([u8; 32], [u8; 32]) {
+    let mut okm = [0; 64];
+    Hkdf::<Sha256>::new(Some(salt.as_slice()), ikm)
+        .expand(&[], &mut okm)
+        .expect("valid output length");
+    let mut first = [0; 32];
+    let mut second = [0; 32];
+    first.copy_from_slice(&okm[..32]);
+    second.copy_from_slice(&okm[32..]);
+    (first, second)
+}
+
+/// 32 zero bits followed by the little endian counter
+fn nonce(counter: u64) -> Nonce {
+    let mut nonce = [0; 12];
+    nonce[4..].copy_from_slice(&counter.to_le_bytes());
+    Nonce::clone_from_slice(&nonce)
+}
+
+fn encrypt(key: &[u8; 32], counter: u64, ad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut buf = plaintext.to_vec();
+    let tag = ChaCha20Poly1305::new(Key::from_slice(key))
+        .encrypt_in_place_detached(&nonce(counter), ad, &mut buf)
+        .expect("message within the ChaCha20 limits");
+    buf.extend_from_slice(&tag);
+    buf
+}
+
+fn decrypt(key: &[u8; 32], counter: u64, ad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, LnError> {
+    if ciphertext.len() < TAG_SIZE {
+        return Err(LnError::Decryption);
+    }
+    let (ciphertext, tag) = ciphertext.split_at(ciphertext.len() - TAG_SIZE);
+    let mut buf = ciphertext.to_vec();
+    ChaCha20Poly1305::new(Key::from_slice(key))
+        .decrypt_in_place_detached(&nonce(counter), ad, &mut buf, Tag::from_slice(tag))
+        .map_err(|_| LnError::Decryption)?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data_encoding::HEXLOWER;
+
+    fn key(byte: u8) -> SecretKey {
+        SecretKey::from_slice(&[byte; 32]).unwrap()
+    }
+
+    fn hex(data: &[u8]) -> String {
+        HEXLOWER.encode(data)
+    }
+
+    /// The test vectors of BOLT #8
+    #[test]
+    fn test_handshake_vectors() {
+        let remote = key(0x21);
+        let (initiator, act_one) =
+            Initiator::new(key(0x11), key(0x12), &remote.public_key(SECP256K1));
+        assert_eq!(
+            hex(&act_one),
+            "00036360e856310ce5d294e8be33fc807077dc56ac80d95d9cd4ddbd21325eff73f70df6086551151f58b8afe6c195782c6a"
+        );
+
+        let (responder, act_two) = Responder::new(&remote, key(0x22), &act_one).unwrap();
+        assert_eq!(
+            hex(&act_two),
+            "0002466d7fcae563e5cb09a0d1870bb580344804617879a14949cf22285f1bae3f276e2470b93aac583c9ef6eafca3f730ae"
+        );
+
+        let (act_three, mut initiator) = initiator.finish(&act_two).unwrap();
+        assert_eq!(
+            hex(&act_three),
+            "00b9e3a702e93e3a9948c2ed6e5fd7590a6e1c3a0344cfc9d5b57357049aa22355361aa02e55a8fc28fef5bd6d71ad0c38228dc68b1c466263b47fdf31e560e139ba"
+        );
+        assert_eq!(
+            hex(&initiator.sending.key),
+            "969ab31b4d288cedf6218839b27a3e2140827047f2c0f01bf5c04435d43511a9"
+        );
+        assert_eq!(
+            hex(&initiator.receiving.key),
+            "bb9020b8965f4df047e07f955f3c4b88418984aadc5cdb35096b9ea8fa5c3442"
+        );
+
+        let (initiator_key, mut responder) = responder.finish(&act_three).unwrap();
+        assert_eq!(initiator_key, key(0x11).public_key(SECP256K1));
+        assert_eq!(responder.receiving.key, initiator.sending.key);
+
+        // Verify the encryption of the messages, and the rotation of the keys
+        for i in 0..1002 {
+            let packet = initiator.encrypt(b"hello").unwrap();
+            match i {
+                0 => assert_eq!(
+                    hex(&packet),
+                    "cf2b30ddf0cf3f80e7c35a6e6730b59fe802473180f396d88a8fb0db8cbcf25d2f214cf9ea1d95"
+                ),
+                1 => assert_eq!(
+                    hex(&packet),
+                    "72887022101f0b6753e0c7de21657d35a4cb2a1f5cde2650528bbc8f837d0f0d7ad833b1a256a1"
+                ),
+                500 => assert_eq!(
+                    hex(&packet),
+                    "178cb9d7387190fa34db9c2d50027d21793c9bc2d40b1e14dcf30ebeeeb220f48364f7a4c68bf8"
+                ),
+                1000 => assert_eq!(
+                    hex(&packet),
+                    "4a2f3cc3b5e78ddb83dcb426d9863d9d9a723b0337c89dd0b005d89f8d3c05c52b76b29b740f09"
+                ),
+                _ => (),
+            }
+            let len = responder.receiving.decrypt(&packet[..18]).unwrap();
+            assert_eq!(len, [0, 5]);
+            assert_eq!(
+                responder.receiving.decrypt(&packet[18..]).unwrap(),
+                b"hello"
+            );
+        }
+    }
+
+    #[test]
+    fn test_handshake_wrong_key() {
+        // Verify that act one sent to another node is rejected
+        let (_, act_one) = Initiator::new(key(0x11), key(0x12), &key(0x21).public_key(SECP256K1));
+        assert!(matches!(
+            Responder::new(&key(0x31), key(0x22), &act_one),
+            Err(LnError::Decryption)
+        ));
+    }
+}
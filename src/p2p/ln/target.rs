@@ -0,0 +1,74 @@
+use secp256k1::PublicKey;
+use std::{fmt, str::FromStr};
+
+use crate::p2p::connect::Destination;
+
+/// [`DEFAULT_PORT`] is the port the nodes listen on when the target has none.
+pub const DEFAULT_PORT: u16 = 9735;
+
+/// A handshake target given on the command line: `<node_id>@<host>[:<port>]`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Target {
+    /// Static key of the node, authenticated by the noise handshake
+    pub node_id: PublicKey,
+    pub destination: Destination,
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (node_id, address) = s
+            .split_once('@')
+            .ok_or("expected <node_id>@<host>:<port>")?;
+        let node_id = node_id
+            .parse()
+            .map_err(|err| format!("invalid node id: {}", err))?;
+        let destination = match address.parse() {
+            Ok(destination) => destination,
+            // Only the hostnames and IPv4 addresses may omit the port
+            Err(_) if !address.contains(':') => format!("{}:{}", address, DEFAULT_PORT).parse()?,
+            Err(err) => return Err(err),
+        };
+        Ok(Self {
+            node_id,
+            destination,
+        })
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}", self.node_id, self.destination)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NODE_ID: &str = "03864ef025fde8fb587d989186ce6a4a186895ee44a926bfc370e2c366597a3f8f";
+
+    #[test]
+    fn test_parse_target() {
+        let target: Target = format!("{}@3.33.236.230:9735", NODE_ID).parse().unwrap();
+        assert_eq!(target.node_id.to_string(), NODE_ID);
+        assert_eq!(
+            target.destination,
+            Destination::Addr("3.33.236.230:9735".parse().unwrap())
+        );
+        assert_eq!(target.to_string(), format!("{}@3.33.236.230:9735", NODE_ID));
+
+        let target: Target = format!("{}@node.example.org", NODE_ID).parse().unwrap();
+        assert_eq!(
+            target.destination,
+            Destination::Host {
+                host: "node.example.org".to_string(),
+                port: DEFAULT_PORT
+            }
+        );
+
+        assert!("3.33.236.230:9735".parse::<Target>().is_err());
+        assert!("03864e@3.33.236.230:9735".parse::<Target>().is_err());
+    }
+}
@@ -0,0 +1,72 @@
+use std::{
+    fmt::Debug,
+    future::Future,
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+use tracing::trace;
+
+/// What a mock node received so far, over all its connections
+pub type Received<T> = Arc<Mutex<Vec<T>>>;
+
+/// A node listening on a local port, answering each connection with its serve function, stopped
+/// when dropped
+#[derive(Debug)]
+pub struct MockServer<T> {
+    addr: SocketAddr,
+    received: Received<T>,
+    handle: JoinHandle<()>,
+}
+
+impl<T: Clone + Send + 'static> MockServer<T> {
+    /// Listen on a random local port and answer every connection with `serve`, given the stream,
+    /// the address of the client and the log of the received items
+    pub async fn spawn<F, Fut, E>(serve: F) -> io::Result<Self>
+    where
+        F: Fn(TcpStream, SocketAddr, Received<T>) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Debug,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let received = Received::default();
+
+        let log = received.clone();
+        let handle = tokio::spawn(async move {
+            while let Ok((stream, peer)) = listener.accept().await {
+                let connection = serve(stream, peer, log.clone());
+                tokio::spawn(async move {
+                    if let Err(err) = connection.await {
+                        trace!(?err, "mock connection failed");
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            addr,
+            received,
+            handle,
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Items received so far, over all the connections
+    pub fn received(&self) -> Vec<T> {
+        self.received.lock().unwrap().clone()
+    }
+}
+
+impl<T> Drop for MockServer<T> {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
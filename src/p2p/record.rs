@@ -0,0 +1,238 @@
+use bytes::{Bytes, BytesMut};
+use data_encoding::HEXLOWER;
+use futures::{ready, Sink, Stream};
+use pin_project::pin_project;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+use tracing::{debug, warn};
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// From the client to the peer
+    Sent,
+    /// From the peer to the client
+    Received,
+}
+
+/// A single framed message, raw for btc and post-ECIES plaintext for eth
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Frame {
+    /// Time since the start of the session (in µs)
+    pub elapsed_us: u64,
+    pub direction: Direction,
+    #[serde(serialize_with = "to_hex", deserialize_with = "from_hex")]
+    pub data: Vec<u8>,
+}
+
+/// The messages exchanged with a peer during a handshake
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Session {
+    pub protocol: String,
+    pub target: String,
+    /// Unix time the session started at (in ms)
+    pub started: u64,
+    /// The error the handshake failed with
+    pub error: Option<String>,
+    pub frames: Vec<Frame>,
+    /// Whether the peer closed the connection after its last frame
+    #[serde(default)]
+    pub closed: bool,
+}
+
+impl Session {
+    /// Write the session to a new file of `dir`, returning its path
+    pub fn save(&self, dir: &Path) -> io::Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let target: String = self
+            .target
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' => c,
+                _ => '_',
+            })
+            .collect();
+        let path = dir.join(format!(
+            "{}-{}-{}-{:08x}.json",
+            self.protocol,
+            self.started,
+            target,
+            rand::random::<u32>()
+        ));
+        fs::write(&path, serde_json::to_vec_pretty(self)?)?;
+        Ok(path)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+}
+
+/// Collects the frames of a session, shared by the streams it is given to
+#[derive(Clone, Debug)]
+pub struct Recorder {
+    started: SystemTime,
+    start: Instant,
+    frames: Arc<Mutex<Vec<Frame>>>,
+    closed: Arc<AtomicBool>,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self {
+            started: SystemTime::now(),
+            start: Instant::now(),
+            frames: Arc::default(),
+            closed: Arc::default(),
+        }
+    }
+}
+
+impl Recorder {
+    pub fn record(&self, direction: Direction, data: &[u8]) {
+        self.frames.lock().unwrap().push(Frame {
+            elapsed_us: self.start.elapsed().as_micros() as u64,
+            direction,
+            data: data.to_vec(),
+        });
+    }
+
+    /// Record that the peer closed the connection
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+    }
+
+    /// The session recorded so far
+    pub fn session(&self, protocol: &str, target: String, error: Option<String>) -> Session {
+        Session {
+            protocol: protocol.to_string(),
+            target,
+            started: self
+                .started
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_millis() as u64),
+            error,
+            frames: self.frames.lock().unwrap().clone(),
+            closed: self.closed.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Save the session recorded so far to a new file of `dir`
+    pub fn save(&self, dir: &Path, protocol: &str, target: String, error: Option<String>) {
+        let session = self.session(protocol, target, error);
+        match session.save(dir) {
+            Ok(path) => debug!(
+                "[{}] session recorded to {}",
+                session.target,
+                path.display()
+            ),
+            Err(err) => warn!("[{}] failed to record the session: {}", session.target, err),
+        }
+    }
+}
+
+/// A framed stream recording the frames going through it
+#[pin_project]
+#[derive(Debug)]
+pub struct Tap<S> {
+    #[pin]
+    inner: S,
+    recorder: Option<Recorder>,
+}
+
+impl<S> Tap<S> {
+    pub fn new(inner: S, recorder: Option<Recorder>) -> Self {
+        Self { inner, recorder }
+    }
+}
+
+impl<S> Stream for Tap<S>
+where
+    S: Stream<Item = io::Result<BytesMut>>,
+{
+    type Item = io::Result<BytesMut>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let item = ready!(this.inner.poll_next(cx));
+        match (&item, this.recorder) {
+            (Some(Ok(frame)), Some(recorder)) => recorder.record(Direction::Received, frame),
+            (None, Some(recorder)) => recorder.close(),
+            _ => (),
+        }
+        Poll::Ready(item)
+    }
+}
+
+impl<S> Sink<Bytes> for Tap<S>
+where
+    S: Sink<Bytes, Error = io::Error>,
+{
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
+        let this = self.project();
+        if let Some(recorder) = this.recorder {
+            recorder.record(Direction::Sent, &item);
+        }
+        this.inner.start_send(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_close(cx)
+    }
+}
+
+fn to_hex<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&HEXLOWER.encode(data))
+}
+
+fn from_hex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let hex = String::deserialize(deserializer)?;
+    HEXLOWER
+        .decode(hex.as_bytes())
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_roundtrip() {
+        let recorder = Recorder::default();
+        recorder.record(Direction::Sent, &[0xf9, 0xbe, 0xb4, 0xd9]);
+        recorder.record(Direction::Received, &[]);
+        let session = recorder.session("btc", "[::1]:8333".to_string(), None);
+
+        // Verify that the saved session loads back as it was recorded
+        let dir = std::env::temp_dir().join(format!("p2p-handshake-{:08x}", rand::random::<u32>()));
+        let path = session.save(&dir).unwrap();
+        assert!(path
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .contains("-___1__8333-"));
+        assert_eq!(Session::load(&path).unwrap(), session);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+use bytes::{Buf, Bytes, BytesMut};
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use reth_ecies::{stream::ECIESStream, util::pk2id};
+use reth_primitives::NodeRecord;
+use secp256k1::{SecretKey, SECP256K1};
+use std::io;
+use tokio::net::TcpListener;
+use tokio_util::codec::{Decoder, Encoder};
+use tracing::trace;
+
+use crate::p2p::{
+    btc::{
+        self,
+        codec::{HEADER_SIZE, MAX_MESSAGE_SIZE},
+    },
+    connect::Endpoints,
+    error::P2PError,
+    eth,
+    record::{Direction, Frame, Session},
+};
+
+/// Splits the raw bitcoin frames without validating them, so recorded garbage replays as is, up to
+/// the message size accepted by the live codec
+#[derive(Debug, Default)]
+struct BtcFrameCodec;
+
+impl Decoder for BtcFrameCodec {
+    type Item = BytesMut;
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if buf.len() < HEADER_SIZE {
+            return Ok(None);
+        }
+        let mut length = &buf[16..20];
+        let size = HEADER_SIZE + length.get_u32_le() as usize;
+        if size > HEADER_SIZE + MAX_MESSAGE_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "bitcoin message too large",
+            ));
+        }
+        if buf.len() < size {
+            return Ok(None);
+        }
+        Ok(Some(buf.split_to(size)))
+    }
+}
+
+impl Encoder<Bytes> for BtcFrameCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Bytes, buf: &mut BytesMut) -> Result<(), Self::Error> {
+        buf.extend_from_slice(&item);
+        Ok(())
+    }
+}
+
+/// Play the peer side of a recorded session back against the client, then perform the
+/// handshake of the session protocol with it
+pub async fn replay(session: &Session, timeout: u64) -> Result<Endpoints, P2PError> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let (frames, closed) = (session.frames.clone(), session.closed);
+
+    let chain = btc::chain::Chain::from_name(&session.protocol);
+    let (peer, res) = match (chain, session.protocol.as_str()) {
+        (Some(chain), _) => {
+            let profile = chain.profile();
+            let peer = tokio::spawn(async move {
+                let (stream, _) = listener.accept().await?;
+                play(BtcFrameCodec.framed(stream), frames, closed).await
+            });
+            let res = btc::handshake(btc::Config {
+                node_address: addr.into(),
+                timeout,
+                user_agent: profile.user_agent.to_string(),
+                profile,
+                connector: Default::default(),
+                record: None,
+            })
+            .await
+            .map(|peer| peer.endpoints);
+            (peer, res)
+        }
+        (None, "eth") => {
+            // The key of the recorded peer is unknown, the replayed node has its own
+            let key = SecretKey::new(&mut rand::thread_rng());
+            let peer = tokio::spawn(async move {
+                let (stream, _) = listener.accept().await?;
+                let ecies = ECIESStream::incoming(stream, key)
+                    .await
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                play(ecies, frames, closed).await
+            });
+            let res = eth::handshake(eth::Config {
+                timeout,
+                peer: NodeRecord::new(addr, pk2id(&key.public_key(SECP256K1))),
+                host: None,
+                connector: Default::default(),
+                record: None,
+            })
+            .await;
+            (peer, res)
+        }
+        (None, protocol) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown session protocol: {protocol}"),
+            )
+            .into())
+        }
+    };
+
+    peer.abort();
+    res
+}
+
+/// Send the frames received from the peer in their recorded order, waiting for a frame of the
+/// client for each frame it sent
+async fn play<T>(mut transport: T, frames: Vec<Frame>, closed: bool) -> io::Result<()>
+where
+    T: Stream<Item = io::Result<BytesMut>> + Sink<Bytes, Error = io::Error> + Unpin,
+{
+    for frame in frames {
+        match frame.direction {
+            Direction::Received => transport.send(frame.data.into()).await?,
+            Direction::Sent => {
+                if transport.next().await.transpose()?.is_none() {
+                    trace!("client closed the connection during the replay");
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    // Close the connection like the recorded peer, or keep it open until the client closes it
+    if closed {
+        return Ok(());
+    }
+    while transport.next().await.transpose()?.is_some() {}
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use async_trait::async_trait;
+use hickory_resolver::{error::ResolveErrorKind, TokioAsyncResolver};
+use std::{collections::HashMap, io, net::IpAddr};
+
+/// A source of the DNS records used to resolve hostname targets and node lists
+#[async_trait]
+pub trait Resolver: Send + Sync {
+    /// Look up every IPv4 and IPv6 address of `host`
+    async fn lookup_ip(&self, host: &str) -> io::Result<Vec<IpAddr>>;
+
+    /// Look up the TXT record of `name`, joining its character strings
+    async fn lookup_txt(&self, name: &str) -> io::Result<Option<String>>;
+}
+
+#[async_trait]
+impl Resolver for TokioAsyncResolver {
+    async fn lookup_ip(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+        match TokioAsyncResolver::lookup_ip(self, host).await {
+            Ok(lookup) => Ok(lookup.iter().collect()),
+            Err(err) if matches!(err.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+                Ok(Vec::new())
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn lookup_txt(&self, name: &str) -> io::Result<Option<String>> {
+        let lookup = match self.txt_lookup(name).await {
+            Ok(lookup) => lookup,
+            Err(err) if matches!(err.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+                return Ok(None)
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(lookup.iter().next().map(|txt| {
+            txt.txt_data()
+                .iter()
+                .map(|data| String::from_utf8_lossy(data))
+                .collect()
+        }))
+    }
+}
+
+/// A resolver serving records from an in-memory zone
+#[derive(Clone, Debug, Default)]
+pub struct StaticResolver {
+    addrs: HashMap<String, Vec<IpAddr>>,
+    txts: HashMap<String, String>,
+}
+
+impl StaticResolver {
+    /// Add the addresses of `host` to the zone
+    pub fn insert_ip(&mut self, host: impl Into<String>, addrs: Vec<IpAddr>) {
+        self.addrs.insert(host.into().to_ascii_lowercase(), addrs);
+    }
+
+    /// Add the TXT record of `name` to the zone
+    pub fn insert_txt(&mut self, name: impl Into<String>, txt: impl Into<String>) {
+        self.txts
+            .insert(name.into().to_ascii_lowercase(), txt.into());
+    }
+}
+
+#[async_trait]
+impl Resolver for StaticResolver {
+    async fn lookup_ip(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+        Ok(self
+            .addrs
+            .get(&host.to_ascii_lowercase())
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn lookup_txt(&self, name: &str) -> io::Result<Option<String>> {
+        Ok(self.txts.get(&name.to_ascii_lowercase()).cloned())
+    }
+}
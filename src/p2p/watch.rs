@@ -0,0 +1,185 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+/// [`LATENCY_HISTORY`] is the number of handshake latencies kept per peer.
+pub const LATENCY_HISTORY: usize = 16;
+
+/// Health of a single peer across the handshake rounds
+#[derive(Clone, Debug, Default)]
+pub struct PeerState {
+    pub up: bool,
+    pub consecutive_failures: u32,
+    pub last_seen: Option<SystemTime>,
+    /// Latencies of the most recent successful handshakes, oldest first
+    pub latencies: VecDeque<Duration>,
+}
+
+impl PeerState {
+    /// Average latency of the recorded handshakes
+    pub fn average_latency(&self) -> Option<Duration> {
+        let count = u32::try_from(self.latencies.len())
+            .ok()
+            .filter(|n| *n > 0)?;
+        Some(self.latencies.iter().sum::<Duration>() / count)
+    }
+}
+
+/// A change of the health of a peer, the only events reported in watch mode
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Transition {
+    Up {
+        target: String,
+        latency: Duration,
+        average_latency: Duration,
+        /// Number of failed rounds before the peer came back up
+        failures: u32,
+    },
+    Down {
+        target: String,
+        last_seen: Option<SystemTime>,
+        error: String,
+    },
+}
+
+impl fmt::Display for Transition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transition::Up {
+                target,
+                latency,
+                average_latency,
+                failures,
+            } => write!(
+                f,
+                "[up] [{}] latency: {:?} average: {:?} after {} failed rounds",
+                target, latency, average_latency, failures
+            ),
+            Transition::Down {
+                target,
+                last_seen,
+                error,
+            } => {
+                let last_seen = last_seen.map_or("never".to_string(), |time| {
+                    humantime::format_rfc3339_seconds(time).to_string()
+                });
+                write!(
+                    f,
+                    "[down] [{}] last seen: {} error: {}",
+                    target, last_seen, error
+                )
+            }
+        }
+    }
+}
+
+/// Tracks the state of every watched peer across the handshake rounds
+#[derive(Debug, Default)]
+pub struct Monitor {
+    peers: HashMap<String, PeerState>,
+}
+
+impl Monitor {
+    /// The state of a peer, once it has been handshaked at least once
+    pub fn peer(&self, target: &str) -> Option<&PeerState> {
+        self.peers.get(target)
+    }
+
+    /// Record a successful handshake, returning the transition if the peer was not up
+    pub fn record_up(
+        &mut self,
+        target: &str,
+        latency: Duration,
+        now: SystemTime,
+    ) -> Option<Transition> {
+        let state = self.peers.entry(target.to_string()).or_default();
+        if state.latencies.len() == LATENCY_HISTORY {
+            state.latencies.pop_front();
+        }
+        state.latencies.push_back(latency);
+        state.last_seen = Some(now);
+
+        let failures = std::mem::take(&mut state.consecutive_failures);
+        if state.up {
+            return None;
+        }
+        state.up = true;
+        Some(Transition::Up {
+            target: target.to_string(),
+            latency,
+            average_latency: state.average_latency().unwrap_or(latency),
+            failures,
+        })
+    }
+
+    /// Record a failed handshake, returning the transition if the peer was up or never seen
+    pub fn record_down(&mut self, target: &str, error: String) -> Option<Transition> {
+        let known = self.peers.contains_key(target);
+        let state = self.peers.entry(target.to_string()).or_default();
+        state.consecutive_failures += 1;
+        if known && !state.up {
+            return None;
+        }
+        state.up = false;
+        Some(Transition::Down {
+            target: target.to_string(),
+            last_seen: state.last_seen,
+            error,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transitions() {
+        let mut monitor = Monitor::default();
+        let now = SystemTime::now();
+        let ms = Duration::from_millis;
+
+        // Verify that the first round reports every peer, then only the changes
+        assert!(monitor.record_up("a", ms(10), now).is_some());
+        assert!(monitor.record_down("b", "refused".to_string()).is_some());
+        assert!(monitor.record_up("a", ms(30), now).is_none());
+        assert!(monitor.record_down("b", "refused".to_string()).is_none());
+
+        assert_eq!(
+            monitor.record_down("a", "timeout".to_string()),
+            Some(Transition::Down {
+                target: "a".to_string(),
+                last_seen: Some(now),
+                error: "timeout".to_string()
+            })
+        );
+        assert!(monitor.record_down("a", "timeout".to_string()).is_none());
+        assert_eq!(monitor.peer("a").unwrap().consecutive_failures, 2);
+
+        assert_eq!(
+            monitor.record_up("a", ms(20), now),
+            Some(Transition::Up {
+                target: "a".to_string(),
+                latency: ms(20),
+                average_latency: ms(20),
+                failures: 2
+            })
+        );
+        assert_eq!(monitor.peer("a").unwrap().consecutive_failures, 0);
+    }
+
+    #[test]
+    fn test_latency_history() {
+        let mut monitor = Monitor::default();
+        for latency in 0..(LATENCY_HISTORY as u64 + 4) {
+            monitor.record_up("a", Duration::from_millis(latency), SystemTime::now());
+        }
+
+        // Verify that only the most recent latencies are kept
+        let state = monitor.peer("a").unwrap();
+        assert_eq!(state.latencies.len(), LATENCY_HISTORY);
+        assert_eq!(state.latencies.front(), Some(&Duration::from_millis(4)));
+    }
+}
@@ -1,11 +1,445 @@
-use tracing_subscriber::{filter::LevelFilter, EnvFilter};
+use clap::ValueEnum;
+use metrics::{
+    counter, decrement_gauge, describe_counter, describe_gauge, describe_histogram, gauge,
+    histogram, increment_counter, increment_gauge, Unit,
+};
+use metrics_exporter_prometheus::{BuildError, PrometheusBuilder};
+use opentelemetry::{trace::TraceError, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    runtime,
+    trace::{self, Sampler},
+    Resource,
+};
+use std::{
+    cell::RefCell,
+    future::Future,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tracing::{Level, Subscriber};
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{InitError, RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{
+    filter::{Directive, LevelFilter, Targets},
+    fmt::writer::BoxMakeWriter,
+    layer::SubscriberExt,
+    registry::LookupSpan,
+    util::{SubscriberInitExt, TryInitError},
+    EnvFilter, Layer, Registry,
+};
 
-pub fn init_tracing() {
-    let env_filter = EnvFilter::builder()
+/// [`LATENCY_BUCKETS`] are the histogram buckets (in seconds) of the handshake latencies.
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Format of the log lines
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// How often the log file is rotated
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogRotation {
+    Minutely,
+    Hourly,
+    #[default]
+    Daily,
+    Never,
+}
+
+impl From<LogRotation> for Rotation {
+    fn from(rotation: LogRotation) -> Self {
+        match rotation {
+            LogRotation::Minutely => Rotation::MINUTELY,
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        }
+    }
+}
+
+/// Export of the spans over OTLP
+#[derive(Clone, Debug)]
+pub struct OtlpConfig {
+    /// gRPC endpoint of the collector, e.g. `http://localhost:4317`
+    pub endpoint: String,
+    pub service_name: String,
+    /// Fraction of the traces exported, between 0 and 1
+    pub sample_ratio: f64,
+}
+
+/// Where and how the logs and the spans are written
+#[derive(Clone, Debug, Default)]
+pub struct TracingConfig {
+    pub format: LogFormat,
+    /// Log file written instead of stderr, rotated files get a date suffix
+    pub file: Option<PathBuf>,
+    pub rotation: LogRotation,
+    /// Per-target level directives applied on top of `RUST_LOG`, e.g. `p2p_handshake::p2p::btc=debug`
+    pub filters: Vec<Directive>,
+    pub otlp: Option<OtlpConfig>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum TelemetryError {
+    #[error("{0}: OTLP trace export error")]
+    Trace(#[from] TraceError),
+    #[error("{0}: log file error")]
+    LogFile(#[from] InitError),
+    #[error("{0}: a global subscriber is already installed")]
+    AlreadyInstalled(#[from] TryInitError),
+}
+
+/// Keeps the log file writer running, buffered lines are flushed when it is dropped
+#[derive(Debug)]
+pub struct TracingGuard(Option<WorkerGuard>);
+
+/// Install the global subscriber: logs to stderr or to a file, and exports the spans of this
+/// crate over OTLP when configured.
+///
+/// Every handshake is exported as its own trace, with the connection and the protocol phases
+/// as child spans, regardless of the log filters. Returns an error rather than panicking when a
+/// subscriber is already installed, e.g. when embedded in another service.
+pub fn init_tracing(config: TracingConfig) -> Result<TracingGuard, TelemetryError> {
+    let mut filter = EnvFilter::builder()
         .with_default_directive(LevelFilter::INFO.into())
         .from_env_lossy();
-    tracing_subscriber::fmt()
-        .with_env_filter(env_filter)
-        .with_writer(std::io::stderr)
-        .init();
+    for directive in config.filters {
+        filter = filter.add_directive(directive);
+    }
+
+    let (writer, guard) = match &config.file {
+        Some(path) => {
+            let mut builder = RollingFileAppender::builder().rotation(config.rotation.into());
+            if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+                builder = builder.filename_prefix(name);
+            }
+            let directory = path.parent().unwrap_or_else(|| Path::new("."));
+            let (writer, guard) = tracing_appender::non_blocking(builder.build(directory)?);
+            (BoxMakeWriter::new(writer), Some(guard))
+        }
+        None => (BoxMakeWriter::new(std::io::stderr), None),
+    };
+    let fmt: Box<dyn Layer<Registry> + Send + Sync> = match config.format {
+        LogFormat::Text => tracing_subscriber::fmt::layer()
+            .with_writer(writer)
+            .with_ansi(config.file.is_none())
+            .boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_writer(writer)
+            .boxed(),
+    };
+
+    let otel = config.otlp.map(otlp_layer).transpose()?;
+
+    tracing_subscriber::registry()
+        .with(fmt.with_filter(filter))
+        .with(otel)
+        .try_init()?;
+    Ok(TracingGuard(guard))
+}
+
+/// The layer exporting the spans of this crate over OTLP, sampled by trace
+fn otlp_layer<S>(otlp: OtlpConfig) -> Result<impl Layer<S>, TraceError>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(otlp.endpoint),
+        )
+        .with_trace_config(
+            trace::config()
+                .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+                    otlp.sample_ratio,
+                ))))
+                .with_resource(Resource::new(vec![KeyValue::new(
+                    "service.name",
+                    otlp.service_name,
+                )])),
+        )
+        .install_batch(runtime::Tokio)?;
+    let targets = Targets::new().with_target(env!("CARGO_CRATE_NAME"), Level::TRACE);
+    Ok(tracing_opentelemetry::layer()
+        .with_tracer(tracer)
+        .with_filter(targets))
+}
+
+/// Flush the spans not exported yet
+pub fn shutdown_tracing() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// Serve the Prometheus metrics on `addr`, on the `/metrics` path (or any other)
+pub fn init_metrics(addr: SocketAddr) -> Result<(), BuildError> {
+    PrometheusBuilder::new()
+        .with_http_listener(addr)
+        .set_buckets(LATENCY_BUCKETS)?
+        .install()?;
+    describe_metrics();
+    Ok(())
+}
+
+fn describe_metrics() {
+    describe_counter!(
+        "p2p_handshake_attempts_total",
+        "Number of handshakes started"
+    );
+    describe_counter!(
+        "p2p_handshake_successes_total",
+        "Number of handshakes completed"
+    );
+    describe_counter!(
+        "p2p_handshake_failures_total",
+        "Number of failed handshakes by error kind"
+    );
+    describe_histogram!(
+        "p2p_handshake_duration_seconds",
+        Unit::Seconds,
+        "Duration of each handshake phase, and of the whole handshake as the `total` phase"
+    );
+    describe_gauge!(
+        "p2p_connections_in_flight",
+        "Number of handshakes in progress"
+    );
+    describe_gauge!(
+        "p2p_peer_up",
+        "Whether the last handshake with the peer succeeded, in watch mode"
+    );
+}
+
+/// Record the state of a watched peer
+pub fn peer_up(target: &str, up: bool) {
+    gauge!("p2p_peer_up", if up { 1.0 } else { 0.0 }, "target" => target.to_string());
+}
+
+/// Handshake metrics of a protocol
+#[derive(Clone, Copy, Debug)]
+pub struct HandshakeMetrics {
+    protocol: &'static str,
+}
+
+tokio::task_local! {
+    /// Phases recorded by the handshake running in the current task, see [`collect_phases`]
+    static PHASES: RefCell<Vec<(&'static str, Duration)>>;
+}
+
+/// Run a handshake, collecting the duration of every phase it records
+pub async fn collect_phases<F: Future>(handshake: F) -> (F::Output, Vec<(&'static str, Duration)>) {
+    PHASES
+        .scope(RefCell::new(Vec::new()), async move {
+            let output = handshake.await;
+            (output, PHASES.with(RefCell::take))
+        })
+        .await
+}
+
+impl HandshakeMetrics {
+    pub fn new(protocol: &'static str) -> Self {
+        Self { protocol }
+    }
+
+    /// Count a handshake attempt, tracking it as in flight until the guard is dropped
+    pub fn attempt(&self) -> InFlight {
+        increment_counter!("p2p_handshake_attempts_total", "protocol" => self.protocol);
+        increment_gauge!("p2p_connections_in_flight", 1.0, "protocol" => self.protocol);
+        InFlight {
+            protocol: self.protocol,
+        }
+    }
+
+    /// Record the duration of a handshake phase
+    pub fn phase(&self, phase: &'static str, duration: Duration) {
+        let _ = PHASES.try_with(|phases| phases.borrow_mut().push((phase, duration)));
+        histogram!(
+            "p2p_handshake_duration_seconds",
+            duration.as_secs_f64(),
+            "protocol" => self.protocol,
+            "phase" => phase
+        );
+    }
+
+    pub fn success(&self, duration: Duration) {
+        increment_counter!("p2p_handshake_successes_total", "protocol" => self.protocol);
+        self.phase("total", duration);
+    }
+
+    pub fn failure(&self, kind: &'static str) {
+        counter!(
+            "p2p_handshake_failures_total",
+            1,
+            "protocol" => self.protocol,
+            "kind" => kind
+        );
+    }
+}
+
+/// A handshake in progress, accounted in the in-flight gauge
+#[derive(Debug)]
+pub struct InFlight {
+    protocol: &'static str,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        decrement_gauge!("p2p_connections_in_flight", 1.0, "protocol" => self.protocol);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::TraceContextExt;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    #[test]
+    fn test_init_tracing_twice() {
+        let _guard = init_tracing(TracingConfig {
+            format: LogFormat::Json,
+            filters: vec!["p2p_handshake::p2p::btc=debug".parse().unwrap()],
+            ..Default::default()
+        });
+
+        // Verify that installing a second subscriber fails without panicking
+        assert!(matches!(
+            init_tracing(TracingConfig::default()),
+            Err(TelemetryError::AlreadyInstalled(_))
+        ));
+    }
+
+    /// Whether a span of this crate is sampled by the OTLP layer
+    fn sampled(sample_ratio: f64) -> bool {
+        let layer = otlp_layer(OtlpConfig {
+            endpoint: "http://127.0.0.1:4317".to_string(),
+            service_name: "test".to_string(),
+            sample_ratio,
+        })
+        .unwrap();
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("handshake");
+            span.context().span().span_context().is_sampled()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_otlp_layer() {
+        // Verify that the sample ratio is applied to the spans of the crate
+        assert!(sampled(1.0));
+        assert!(!sampled(0.0));
+    }
+
+    /// The value of the sample of `name` having all the `labels`
+    fn sample<'a>(rendered: &'a str, name: &str, labels: &[&str]) -> Option<&'a str> {
+        rendered
+            .lines()
+            .filter(|line| line.starts_with(&format!("{}{{", name)))
+            .find(|line| labels.iter().all(|label| line.contains(label)))
+            .and_then(|line| line.rsplit(' ').next())
+    }
+
+    #[test]
+    fn test_handshake_metrics() {
+        let handle = PrometheusBuilder::new()
+            .set_buckets(LATENCY_BUCKETS)
+            .unwrap()
+            .install_recorder()
+            .unwrap();
+        describe_metrics();
+
+        let metrics = HandshakeMetrics::new("test");
+        {
+            let _in_flight = metrics.attempt();
+            metrics.phase("connect", Duration::from_millis(3));
+            metrics.success(Duration::from_millis(20));
+        }
+        {
+            let _in_flight = metrics.attempt();
+            metrics.failure("timeout");
+        }
+
+        // Verify the counters, the in-flight gauge and the latency histogram of the protocol
+        let rendered = handle.render();
+        let protocol = r#"protocol="test""#;
+        let sample = |name, labels: &[&str]| sample(&rendered, name, labels).map(str::to_string);
+        assert_eq!(
+            sample("p2p_handshake_attempts_total", &[protocol]).as_deref(),
+            Some("2")
+        );
+        assert_eq!(
+            sample("p2p_handshake_successes_total", &[protocol]).as_deref(),
+            Some("1")
+        );
+        assert_eq!(
+            sample(
+                "p2p_handshake_failures_total",
+                &[protocol, r#"kind="timeout""#]
+            )
+            .as_deref(),
+            Some("1")
+        );
+        assert_eq!(
+            sample("p2p_connections_in_flight", &[protocol]).as_deref(),
+            Some("0")
+        );
+        assert_eq!(
+            sample(
+                "p2p_handshake_duration_seconds_bucket",
+                &[protocol, r#"phase="total""#, r#"le="0.01""#]
+            )
+            .as_deref(),
+            Some("0")
+        );
+        assert_eq!(
+            sample(
+                "p2p_handshake_duration_seconds_bucket",
+                &[protocol, r#"phase="total""#, r#"le="0.025""#]
+            )
+            .as_deref(),
+            Some("1")
+        );
+        assert_eq!(
+            sample(
+                "p2p_handshake_duration_seconds_count",
+                &[protocol, r#"phase="connect""#]
+            )
+            .as_deref(),
+            Some("1")
+        );
+        assert!(rendered.contains("# HELP p2p_handshake_failures_total"));
+    }
+
+    #[tokio::test]
+    async fn test_collect_phases() {
+        let metrics = HandshakeMetrics::new("test");
+        let (output, phases) = collect_phases(async {
+            metrics.phase("connect", Duration::from_millis(3));
+            metrics.phase("hello", Duration::from_millis(5));
+            42
+        })
+        .await;
+
+        // Verify that the phases are collected in order, and only inside the scope
+        assert_eq!(output, 42);
+        assert_eq!(
+            phases,
+            vec![
+                ("connect", Duration::from_millis(3)),
+                ("hello", Duration::from_millis(5))
+            ]
+        );
+        metrics.phase("total", Duration::from_millis(8));
+    }
 }
@@ -0,0 +1,74 @@
+use libp2p::Multiaddr;
+use p2p_handshake::p2p::{
+    beacon::{
+        handshake,
+        mock::{MockConfig, MockNode},
+        network::{ForkDigest, Network},
+        rpc::Status,
+        Config,
+    },
+    connect::{Destination, Endpoints},
+    error::{BeaconError, P2PError},
+};
+
+async fn handshake_with(
+    address: Multiaddr,
+    network: Network,
+    fork_digest: Option<ForkDigest>,
+) -> Result<Endpoints, P2PError> {
+    handshake(Config {
+        address,
+        timeout: 500,
+        connector: Default::default(),
+        network,
+        fork_digest,
+    })
+    .await
+}
+
+#[tokio::test]
+async fn test_beacon_handshake() {
+    let node = MockNode::spawn(MockConfig::default()).await.unwrap();
+    let res = handshake_with(node.address(), Network::Mainnet, None).await;
+
+    // Verify that the status then the metadata were requested from the mock node
+    assert_eq!(res.unwrap().remote, Destination::from(node.addr()));
+    assert_eq!(
+        node.received(),
+        vec![
+            "/eth2/beacon_chain/req/status/1/ssz_snappy",
+            "/eth2/beacon_chain/req/metadata/2/ssz_snappy"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_beacon_handshake_fork_digest() {
+    let node = MockNode::spawn(MockConfig {
+        status: Status {
+            fork_digest: [0xde, 0xad, 0xbe, 0xef],
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+
+    // Verify that a node on another network is reported
+    match handshake_with(node.address(), Network::Sepolia, None).await {
+        Err(P2PError::BeaconError(BeaconError::ForkDigestMismatch { digest, network })) => {
+            assert_eq!(digest, "deadbeef");
+            assert_eq!(network, "sepolia");
+        }
+        res => panic!("expected a fork digest mismatch, got {:?}", res),
+    }
+
+    // Verify that a configured fork digest overrides the network
+    let res = handshake_with(
+        node.address(),
+        Network::Mainnet,
+        Some([0xde, 0xad, 0xbe, 0xef]),
+    )
+    .await;
+    assert!(res.is_ok());
+}
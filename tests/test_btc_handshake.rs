@@ -1,27 +1,197 @@
 use p2p_handshake::p2p::{
-    btc::{handshake, Config},
-    config::HANDSHAKE_TIMEOUT,
+    btc::{
+        chain::{Chain, Profile},
+        handshake,
+        mock::{Behavior, MockConfig, MockPeer},
+        Config, Peer, USER_AGENT,
+    },
+    connect::Destination,
+    error::P2PError,
 };
+use std::{io, time::Duration};
+
+/// Perform the P2P handshake with a mock peer behaving as scripted
+async fn handshake_with(behavior: Behavior) -> (MockPeer, Result<Peer, P2PError>) {
+    let peer = MockPeer::spawn(behavior.into()).await.unwrap();
+    let res = handshake(Config {
+        node_address: peer.addr().into(),
+        timeout: 500,
+        user_agent: USER_AGENT.to_string(),
+        profile: Default::default(),
+        connector: Default::default(),
+        record: None,
+    })
+    .await;
+    (peer, res)
+}
+
+/// The first `count` messages received by the mock peer
+async fn received(peer: &MockPeer, count: usize) -> Vec<&'static str> {
+    // The messages sent after the version of the peer may still be in flight
+    tokio::time::timeout(Duration::from_secs(1), async {
+        loop {
+            let received = peer.received();
+            if received.len() >= count {
+                return received[..count].to_vec();
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap()
+}
+
+fn io_error_kind(res: Result<Peer, P2PError>) -> io::ErrorKind {
+    match res {
+        Err(P2PError::IOError(err)) => err.kind(),
+        res => panic!("expected an IO error, got {:?}", res),
+    }
+}
 
 #[tokio::test]
 async fn test_btc_handshake() {
-    // Use the hardcoded nodes to perform the P2P handshake
-    let nodes_addrs = vec![
-        "178.238.233.75:8333",
-        "108.208.224.205:8333",
-        "54.228.116.186:48333",
-        "96.126.123.143:8333",
-    ];
-
-    for address in nodes_addrs {
-        // Iterate over the nodes and perform the P2P handshake
-        let res = handshake(Config {
-            node_address: address.parse().unwrap(),
-            timeout: HANDSHAKE_TIMEOUT,
-            user_agent: "/Satoshi:25.0.0/".to_string(),
+    let (peer, res) = handshake_with(Behavior::Honest).await;
+
+    // Verify that the handshake completed with the mock peer, and reports its version
+    let handshaked = res.unwrap();
+    assert_eq!(handshaked.endpoints.remote, Destination::from(peer.addr()));
+    assert_eq!(
+        handshaked.version,
+        Chain::Bitcoin.profile().protocol_version
+    );
+    assert_eq!(handshaked.user_agent, "/mock:0.1.0/");
+
+    assert_eq!(
+        received(&peer, 3).await,
+        vec!["version", "sendaddrv2", "verack"]
+    );
+}
+
+#[tokio::test]
+async fn test_btc_handshake_verack_first() {
+    let (_peer, res) = handshake_with(Behavior::VerackFirst).await;
+
+    // Verify that the messages of the peer are accepted in any order
+    assert!(res.is_ok());
+}
+
+#[tokio::test]
+async fn test_btc_handshake_stall() {
+    let (_peer, res) = handshake_with(Behavior::Stall).await;
+
+    // Verify that a silent peer makes the handshake time out
+    assert!(matches!(res, Err(P2PError::TokioElapsedError(_))));
+}
+
+#[tokio::test]
+async fn test_btc_handshake_garbage() {
+    let (_peer, res) = handshake_with(Behavior::Garbage).await;
+
+    assert_eq!(io_error_kind(res), io::ErrorKind::InvalidData);
+}
+
+#[tokio::test]
+async fn test_btc_handshake_wrong_magic() {
+    let (_peer, res) = handshake_with(Behavior::WrongMagic).await;
+
+    // Verify that the peers of another network are rejected
+    assert_eq!(io_error_kind(res), io::ErrorKind::InvalidData);
+}
+
+#[tokio::test]
+async fn test_btc_handshake_disconnect() {
+    let (_peer, res) = handshake_with(Behavior::Disconnect).await;
+
+    assert_eq!(io_error_kind(res), io::ErrorKind::UnexpectedEof);
+}
+
+#[tokio::test]
+async fn test_btc_handshake_chains() {
+    for chain in [Chain::Litecoin, Chain::Dogecoin] {
+        let peer = MockPeer::spawn(MockConfig {
+            profile: chain.profile(),
+            ..Default::default()
         })
-        .await;
+        .await
+        .unwrap();
+        let config = |profile: Profile| Config {
+            node_address: peer.addr().into(),
+            timeout: 500,
+            user_agent: profile.user_agent.to_string(),
+            profile,
+            connector: Default::default(),
+            record: None,
+        };
 
-        assert!(res.is_ok());
+        // Verify that the handshake completes with the nodes of the same chain only
+        assert!(handshake(config(chain.profile())).await.is_ok());
+        let res = handshake(config(Chain::Bitcoin.profile())).await;
+        assert_eq!(io_error_kind(res), io::ErrorKind::InvalidData);
     }
 }
+
+#[tokio::test]
+async fn test_zcash_handshake() {
+    let zcash = Chain::Zcash.profile();
+    let handshake_zcash = |peer: &MockPeer| {
+        handshake(Config {
+            node_address: peer.addr().into(),
+            timeout: 500,
+            user_agent: zcash.user_agent.to_string(),
+            profile: zcash,
+            connector: Default::default(),
+            record: None,
+        })
+    };
+
+    // Verify that addrv2 support is not signalled to the zcash nodes
+    let peer = MockPeer::spawn(MockConfig {
+        profile: zcash,
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+    let handshaked = handshake_zcash(&peer).await.unwrap();
+    assert_eq!(received(&peer, 2).await, vec!["version", "verack"]);
+
+    // Verify that the network upgrade of the peer is reported
+    assert_eq!(handshaked.upgrade, Some("NU6"));
+    assert!(handshaked.info().starts_with("version: 170120 (NU6) "));
+
+    // Verify that the peers not supporting the last network upgrade are rejected
+    let peer = MockPeer::spawn(MockConfig {
+        profile: Profile {
+            protocol_version: 170_100,
+            ..zcash
+        },
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+    let res = handshake_zcash(&peer).await;
+    assert_eq!(io_error_kind(res), io::ErrorKind::InvalidData);
+}
+
+#[tokio::test]
+async fn test_bitcoin_cash_handshake() {
+    let bch = Chain::BitcoinCash.profile();
+    let peer = MockPeer::spawn(MockConfig {
+        profile: bch,
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+    let res = handshake(Config {
+        node_address: peer.addr().into(),
+        timeout: 500,
+        user_agent: bch.user_agent.to_string(),
+        profile: bch,
+        connector: Default::default(),
+        record: None,
+    })
+    .await;
+
+    // Verify that the witness and addrv2 feature messages are not sent to the nodes
+    assert!(res.is_ok());
+    assert_eq!(received(&peer, 2).await, vec!["version", "verack"]);
+}
@@ -1,22 +1,129 @@
 use p2p_handshake::p2p::{
-    config::HANDSHAKE_TIMEOUT,
-    eth::{handshake, Config},
+    connect::{Destination, Endpoints},
+    error::P2PError,
+    eth::{
+        handshake,
+        mock::{Behavior, MockPeer},
+        Config,
+    },
 };
-use reth_primitives::holesky_nodes;
+use reth_ecies::error::ECIESErrorImpl;
+use reth_eth_wire::{
+    errors::{P2PHandshakeError, P2PStreamError},
+    DisconnectReason,
+};
+use std::time::Duration;
+
+/// Perform the P2P handshake with a mock node behaving as scripted
+async fn handshake_with(
+    behavior: Behavior,
+    timeout: u64,
+) -> (MockPeer, Result<Endpoints, P2PError>) {
+    let peer = MockPeer::spawn(behavior.into()).await.unwrap();
+    let res = handshake(Config {
+        timeout,
+        peer: peer.record(),
+        host: None,
+        connector: Default::default(),
+        record: None,
+    })
+    .await;
+    (peer, res)
+}
 
 #[tokio::test]
 async fn test_eth_handshake() {
-    // Use the holesky nodes to avoid bothering the Ethereum mainnet
-    let nodes_addrs = holesky_nodes();
-
-    // Iterate over the nodes and perform the P2P handshake
-    for peer in nodes_addrs {
-        let res = handshake(Config {
-            timeout: HANDSHAKE_TIMEOUT,
-            peer,
-        })
-        .await;
-
-        assert!(res.is_ok());
+    let (peer, res) = handshake_with(Behavior::Honest, 500).await;
+
+    // Verify that the handshake completed with the mock node
+    assert_eq!(
+        res.unwrap().remote,
+        Destination::from(peer.record().tcp_addr())
+    );
+
+    // The disconnect sent after the Hello of the node may still be in flight
+    let received = tokio::time::timeout(Duration::from_secs(1), async {
+        loop {
+            let received = peer.received();
+            if received.len() >= 2 {
+                return received;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(received, vec!["hello", "disconnect"]);
+}
+
+#[tokio::test]
+async fn test_eth_handshake_disconnect() {
+    let (_peer, res) =
+        handshake_with(Behavior::Disconnect(DisconnectReason::TooManyPeers), 500).await;
+
+    // Verify that the disconnect reason of the node is reported
+    match res {
+        Err(P2PError::P2PStreamError(P2PStreamError::HandshakeError(
+            P2PHandshakeError::Disconnected(reason),
+        ))) => assert_eq!(reason, DisconnectReason::TooManyPeers),
+        res => panic!("expected a disconnect, got {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn test_eth_handshake_stall() {
+    let (_peer, res) = handshake_with(Behavior::Stall, 500).await;
+
+    // Verify that a node which never sends Hello makes the handshake time out
+    assert!(matches!(
+        res,
+        Err(P2PError::P2PStreamError(P2PStreamError::HandshakeError(
+            P2PHandshakeError::Timeout
+        )))
+    ));
+}
+
+#[tokio::test]
+async fn test_eth_handshake_silent() {
+    let (_peer, res) = handshake_with(Behavior::Silent, 500).await;
+
+    // Verify that a node which never answers the auth message makes the handshake time out
+    assert!(matches!(res, Err(P2PError::TokioElapsedError(_))));
+}
+
+#[tokio::test]
+async fn test_eth_handshake_wrong_key() {
+    let (_peer, res) = handshake_with(Behavior::WrongKey, 500).await;
+
+    // Verify that a node which does not own the key of its record can't read our auth message,
+    // and closes the connection before sending its ack
+    match res {
+        Err(P2PError::ECIESError(err)) => assert!(
+            matches!(
+                err.inner(),
+                ECIESErrorImpl::UnreadableStream | ECIESErrorImpl::IO(_)
+            ),
+            "expected the connection to be closed, got {:?}",
+            err
+        ),
+        res => panic!("expected an ECIES error, got {:?}", res),
     }
 }
+
+#[tokio::test]
+async fn test_eth_handshake_oversized() {
+    let (_peer, res) = handshake_with(Behavior::Oversized, 5000).await;
+
+    // Verify that the largest frame is read in full and rejected as an invalid Hello, rather
+    // than waited for until the timeout
+    assert!(
+        matches!(
+            res,
+            Err(P2PError::P2PStreamError(P2PStreamError::HandshakeError(
+                P2PHandshakeError::DecodeError(_)
+            )))
+        ),
+        "expected the frame to be rejected, got {:?}",
+        res
+    );
+}
@@ -0,0 +1,71 @@
+use libp2p::{identity::Keypair, Multiaddr};
+use p2p_handshake::p2p::{
+    connect::{Destination, Endpoints},
+    error::{Libp2pError, P2PError},
+    libp2p::{
+        handshake,
+        mock::{Behavior, MockPeer},
+        Config,
+    },
+};
+
+async fn handshake_with(address: Multiaddr) -> Result<Endpoints, P2PError> {
+    handshake(Config {
+        address,
+        timeout: 500,
+        connector: Default::default(),
+    })
+    .await
+}
+
+#[tokio::test]
+async fn test_libp2p_handshake() {
+    let peer = MockPeer::spawn(Behavior::Honest.into()).await.unwrap();
+    let res = handshake_with(peer.address()).await;
+
+    // Verify that noise, yamux and identify were negotiated with the mock peer
+    assert_eq!(res.unwrap().remote, Destination::from(peer.addr()));
+    assert_eq!(
+        peer.received(),
+        vec!["/noise", "/yamux/1.0.0", "/ipfs/id/1.0.0"]
+    );
+
+    // Verify that the peer id is optional
+    let address = format!("/ip4/127.0.0.1/tcp/{}", peer.addr().port());
+    assert!(handshake_with(address.parse().unwrap()).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_libp2p_handshake_peer_id_mismatch() {
+    let peer = MockPeer::spawn(Behavior::Honest.into()).await.unwrap();
+    let expected = Keypair::generate_ed25519().public().to_peer_id();
+    let address = format!("/ip4/127.0.0.1/tcp/{}/p2p/{}", peer.addr().port(), expected);
+
+    // Verify that the handshake fails when another peer answers
+    match handshake_with(address.parse().unwrap()).await {
+        Err(P2PError::Libp2pError(Libp2pError::PeerIdMismatch {
+            expected: e,
+            actual,
+        })) => {
+            assert_eq!(e, expected);
+            assert_eq!(actual, peer.peer_id());
+        }
+        res => panic!("expected a peer id mismatch, got {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn test_libp2p_handshake_failures() {
+    // Verify that a peer not supporting identify is reported
+    let peer = MockPeer::spawn(Behavior::NoIdentify.into()).await.unwrap();
+    let res = handshake_with(peer.address()).await;
+    assert!(matches!(
+        res,
+        Err(P2PError::Libp2pError(Libp2pError::Negotiation(_)))
+    ));
+
+    // Verify that a stalled peer times out
+    let peer = MockPeer::spawn(Behavior::Stall.into()).await.unwrap();
+    let res = handshake_with(peer.address()).await;
+    assert!(matches!(res, Err(P2PError::TokioElapsedError(_))));
+}
@@ -0,0 +1,61 @@
+use bitcoin::Network;
+use p2p_handshake::p2p::{
+    connect::{Destination, Endpoints},
+    error::{LnError, P2PError},
+    ln::{
+        chain_hash, handshake,
+        mock::{Behavior, MockNode},
+        target::Target,
+        Config,
+    },
+};
+use secp256k1::{SecretKey, SECP256K1};
+
+async fn handshake_with(node: Target) -> Result<Endpoints, P2PError> {
+    handshake(Config {
+        node,
+        timeout: 500,
+        connector: Default::default(),
+        network: Network::Bitcoin,
+    })
+    .await
+}
+
+#[tokio::test]
+async fn test_ln_handshake() {
+    let node = MockNode::spawn(Behavior::Honest.into()).await.unwrap();
+    let res = handshake_with(node.target()).await;
+
+    // Verify that the mock node received our init after the noise handshake
+    assert_eq!(res.unwrap().remote, Destination::from(node.addr()));
+    let received = node.received();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].feature_bits(), vec![1, 8, 12, 14]);
+    assert_eq!(received[0].networks, vec![chain_hash(Network::Bitcoin)]);
+}
+
+#[tokio::test]
+async fn test_ln_handshake_failures() {
+    // Verify that a node answering with another key drops the connection at act one
+    let node = MockNode::spawn(Behavior::Honest.into()).await.unwrap();
+    let target = Target {
+        node_id: SecretKey::new(&mut rand::thread_rng()).public_key(SECP256K1),
+        ..node.target()
+    };
+    let res = handshake_with(target).await;
+    assert!(matches!(res, Err(P2PError::LnError(LnError::IOError(_)))));
+    assert!(node.received().is_empty());
+
+    // Verify that a node sending another message first is reported
+    let node = MockNode::spawn(Behavior::SkipInit.into()).await.unwrap();
+    let res = handshake_with(node.target()).await;
+    assert!(matches!(
+        res,
+        Err(P2PError::LnError(LnError::UnexpectedMessage(18)))
+    ));
+
+    // Verify that a stalled node times out
+    let node = MockNode::spawn(Behavior::Stall.into()).await.unwrap();
+    let res = handshake_with(node.target()).await;
+    assert!(matches!(res, Err(P2PError::TokioElapsedError(_))));
+}
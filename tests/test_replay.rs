@@ -0,0 +1,126 @@
+use p2p_handshake::p2p::{
+    btc::{self, mock as btc_mock, USER_AGENT},
+    error::P2PError,
+    eth::{self, mock as eth_mock},
+    record::{Direction, Session},
+    replay::replay,
+};
+use reth_eth_wire::{
+    errors::{P2PHandshakeError, P2PStreamError},
+    DisconnectReason,
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// A new empty directory to record the sessions to
+fn record_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "p2p-handshake-replay-{:08x}",
+        rand::random::<u32>()
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// The single session recorded to `dir`
+fn recorded_session(dir: &Path) -> Session {
+    let paths: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    assert_eq!(paths.len(), 1);
+    let session = Session::load(&paths[0]).unwrap();
+    fs::remove_dir_all(dir).unwrap();
+    session
+}
+
+async fn record_btc(behavior: btc_mock::Behavior) -> Session {
+    let peer = btc_mock::MockPeer::spawn(behavior.into()).await.unwrap();
+    let dir = record_dir();
+    let _ = btc::handshake(btc::Config {
+        node_address: peer.addr().into(),
+        timeout: 500,
+        user_agent: USER_AGENT.to_string(),
+        profile: Default::default(),
+        connector: Default::default(),
+        record: Some(dir.clone()),
+    })
+    .await;
+    recorded_session(&dir)
+}
+
+async fn record_eth(behavior: eth_mock::Behavior) -> Session {
+    let peer = eth_mock::MockPeer::spawn(behavior.into()).await.unwrap();
+    let dir = record_dir();
+    let _ = eth::handshake(eth::Config {
+        timeout: 500,
+        peer: peer.record(),
+        host: None,
+        connector: Default::default(),
+        record: Some(dir.clone()),
+    })
+    .await;
+    recorded_session(&dir)
+}
+
+#[tokio::test]
+async fn test_replay_btc() {
+    let session = record_btc(btc_mock::Behavior::Honest).await;
+
+    // Verify that the raw frames of both sides were recorded
+    assert_eq!(session.protocol, "btc");
+    assert_eq!(session.error, None);
+    let directions: Vec<_> = session.frames.iter().map(|f| f.direction).collect();
+    assert_eq!(
+        directions,
+        vec![
+            Direction::Sent,
+            Direction::Received,
+            Direction::Sent,
+            Direction::Sent,
+            Direction::Received
+        ]
+    );
+
+    // Verify that the replayed peer completes the handshake again
+    assert!(replay(&session, 500).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_replay_btc_failures() {
+    // Verify that the failures replay the same way they were recorded
+    let session = record_btc(btc_mock::Behavior::Garbage).await;
+    assert!(session.error.is_some());
+    match replay(&session, 500).await {
+        Err(P2PError::IOError(err)) => assert_eq!(err.kind(), io::ErrorKind::InvalidData),
+        res => panic!("expected invalid data, got {:?}", res),
+    }
+
+    let session = record_btc(btc_mock::Behavior::Disconnect).await;
+    assert!(session.closed);
+    match replay(&session, 500).await {
+        Err(P2PError::IOError(err)) => assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof),
+        res => panic!("expected end of stream, got {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn test_replay_eth() {
+    let session = record_eth(eth_mock::Behavior::Honest).await;
+    assert_eq!(session.protocol, "eth");
+    assert!(replay(&session, 500).await.is_ok());
+
+    // Verify that the disconnect reason of the recorded peer is replayed
+    let session = record_eth(eth_mock::Behavior::Disconnect(
+        DisconnectReason::AlreadyConnected,
+    ))
+    .await;
+    match replay(&session, 500).await {
+        Err(P2PError::P2PStreamError(P2PStreamError::HandshakeError(
+            P2PHandshakeError::Disconnected(reason),
+        ))) => assert_eq!(reason, DisconnectReason::AlreadyConnected),
+        res => panic!("expected a disconnect, got {:?}", res),
+    }
+}